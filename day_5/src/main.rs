@@ -15,7 +15,12 @@ fn main() {
 
     let mut contents = String::new();
     file.read_to_string(&mut contents).unwrap();
-    let op_list: Vec<String> = contents.split(',').map(|s| s.to_string()).collect();
+    let op_list = match intcode_computer::parse_program(&contents) {
+        Err(why) => panic!("couldn't parse input.txt: {}", why),
+        Ok(op_list) => op_list,
+    };
 
-    intcode_computer::run(op_list);
+    if let Err(why) = intcode_computer::run(op_list) {
+        panic!("Intcode program failed: {}", why);
+    }
 }
@@ -15,7 +15,12 @@ fn main() {
 
     let mut contents = String::new();
     file.read_to_string(&mut contents).unwrap();
-    let program: Vec<String> = contents.split(',').map(|s| s.trim().to_string()).collect();
+    let program = match intcode_computer::parse_program(&contents) {
+        Err(why) => panic!("couldn't parse input.txt: {}", why),
+        Ok(program) => program,
+    };
 
-    intcode_computer::run(program);
+    if let Err(why) = intcode_computer::run(program) {
+        panic!("Intcode program failed: {}", why);
+    }
 }
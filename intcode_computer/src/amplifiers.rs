@@ -0,0 +1,117 @@
+use crate::machine::Machine;
+use crate::network::{Network, NetworkError};
+
+/// How a chain of amplifiers is wired together.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Topology {
+    /// Each amplifier feeds the next and the last one's output is the result.
+    Series,
+    /// As for Series, but the last amplifier also feeds the first, and the signal goes round the
+    /// loop until they all halt.
+    Feedback,
+}
+
+/// Runs copies of one program as a chain of amplifiers. Each amplifier is given its phase setting
+/// as its first input, then the first amplifier is given the input signal.
+#[derive(Debug, Clone)]
+pub struct AmplifierPipeline {
+    program: Vec<i64>,
+    phase_settings: Vec<i64>,
+    input_signal: i64,
+    topology: Topology,
+}
+
+impl AmplifierPipeline {
+    /// Starts a series pipeline with no amplifiers and an input signal of 0.
+    pub fn new(program: &[i64]) -> AmplifierPipeline {
+        AmplifierPipeline {
+            program: program.to_vec(),
+            phase_settings: Vec::new(),
+            input_signal: 0,
+            topology: Topology::Series,
+        }
+    }
+
+    /// Sets the phase of each amplifier, which also decides how many there are.
+    pub fn phase_settings(mut self, phase_settings: &[i64]) -> AmplifierPipeline {
+        self.phase_settings = phase_settings.to_vec();
+        self
+    }
+
+    pub fn input_signal(mut self, input_signal: i64) -> AmplifierPipeline {
+        self.input_signal = input_signal;
+        self
+    }
+
+    pub fn topology(mut self, topology: Topology) -> AmplifierPipeline {
+        self.topology = topology;
+        self
+    }
+
+    /// Runs the amplifiers until they all halt and returns the last signal out of the final one.
+    pub fn run(&self) -> Result<i64, NetworkError> {
+        if self.phase_settings.is_empty() {
+            return Err(NetworkError::NoMachines);
+        }
+
+        let mut network = Network::new();
+        for &phase in &self.phase_settings {
+            let amp = network.add_machine(Machine::new(self.program.clone()));
+            network.push_input(amp, phase);
+        }
+        let last = network.len() - 1;
+        for amp in 0..last {
+            network.link(amp, amp + 1);
+        }
+        if self.topology == Topology::Feedback {
+            network.link(last, 0);
+        }
+        network.push_input(0, self.input_signal);
+
+        network.run_to_completion()?;
+        match network.outputs(last).last() {
+            Some(&signal) => Ok(signal),
+            None => Err(NetworkError::NoOutput(last)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_program;
+
+    #[test]
+    fn test_series() {
+        let program = parse_program("3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0").unwrap();
+        let pipeline = AmplifierPipeline::new(&program).phase_settings(&[4, 3, 2, 1, 0]);
+        assert_eq!(pipeline.run(), Ok(43210));
+        assert_eq!(AmplifierPipeline::new(&program).run(), Err(NetworkError::NoMachines));
+    }
+
+    #[test]
+    fn test_feedback_loop() {
+        let program = parse_program(
+            "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5",
+        )
+        .unwrap();
+        let pipeline = AmplifierPipeline::new(&program)
+            .phase_settings(&[9, 8, 7, 6, 5])
+            .topology(Topology::Feedback);
+        assert_eq!(pipeline.run(), Ok(139629729));
+    }
+
+    #[test]
+    fn test_stalled_pipeline() {
+        // The first amp wants a third input which never comes, so the second never gets a signal
+        let program = parse_program("3,9,3,9,3,9,4,9,99,0").unwrap();
+        match AmplifierPipeline::new(&program).phase_settings(&[0, 0]).run() {
+            Err(NetworkError::Deadlock(blocked)) => {
+                let waiting: Vec<_> =
+                    blocked.iter().map(|amp| (amp.index, amp.ip, amp.input_number)).collect();
+                assert_eq!(waiting, vec![(0, 4, 3), (1, 2, 2)]);
+            }
+            other => panic!("Expected a deadlock, got {:?}", other),
+        }
+    }
+}
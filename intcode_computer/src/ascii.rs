@@ -0,0 +1,111 @@
+use std::collections::VecDeque;
+use std::io;
+use std::io::prelude::*;
+
+use crate::machine::Machine;
+use crate::{run_machine_with_custom_io, run_with_custom_io, IntcodeError, Program};
+
+fn is_ascii(value: i64) -> bool {
+    (0..=127).contains(&value)
+}
+
+/// The codes to feed a program for the given text, one per byte.
+pub fn ascii_codes(text: &str) -> impl Iterator<Item = i64> + '_ {
+    text.bytes().map(i64::from)
+}
+
+/// Output from a program which talks in ASCII.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AsciiOutput {
+    pub text: String,
+    /// Output values which aren't ASCII codes, in the order they were output.
+    pub values: Vec<i64>,
+}
+
+impl AsciiOutput {
+    pub fn new() -> AsciiOutput {
+        AsciiOutput::default()
+    }
+
+    pub fn push(&mut self, value: i64) {
+        if is_ascii(value) {
+            self.text.push(value as u8 as char);
+        } else {
+            self.values.push(value);
+        }
+    }
+
+    pub fn lines(&self) -> Vec<&str> {
+        self.text.lines().collect()
+    }
+}
+
+/// Runs a program which talks in ASCII, giving it the input text and collecting its output.
+pub fn run_ascii(program: Program, input: &str) -> Result<AsciiOutput, IntcodeError> {
+    let mut codes = ascii_codes(input);
+    let mut output = AsciiOutput::new();
+    run_with_custom_io(program, &mut || codes.next(), &mut |value| output.push(value))?;
+    Ok(output)
+}
+
+/// Runs a machine which talks in ASCII, feeding it lines typed at the terminal and printing what
+/// it outputs as text. Output values which aren't ASCII codes are printed as numbers on their own
+/// line.
+pub fn run_machine_ascii_terminal(machine: &mut Machine) -> Result<(), IntcodeError> {
+    let mut pending = VecDeque::new();
+    let mut input = || {
+        if pending.is_empty() {
+            io::stdout().flush().ok()?;
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line).ok()? == 0 {
+                return None;
+            }
+            if !line.ends_with('\n') {
+                line.push('\n');
+            }
+            pending.extend(ascii_codes(&line.replace("\r\n", "\n")));
+        }
+        pending.pop_front()
+    };
+    let mut output = |value| {
+        if is_ascii(value) {
+            print!("{}", value as u8 as char);
+        } else {
+            println!("{}", value);
+        }
+    };
+    run_machine_with_custom_io(machine, &mut input, &mut output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assemble, OpcodeRegistry};
+
+    #[test]
+    fn test_run_ascii() {
+        let program = assemble(
+            "
+            ; Echoes a line in upper case, then outputs 1000
+            loop:   IN [char]
+                    EQ [char], #10, [flag]
+                    JT [flag], #done
+                    LT [char], #97, [flag]
+                    JT [flag], #print
+                    ADD [char], #-32, [char]
+            print:  OUT [char]
+                    JT #1, #loop
+            done:   OUT [char]
+                    OUT #1000
+                    HLT
+            char:   .data 0
+            flag:   .data 0
+            ",
+            &OpcodeRegistry::standard(),
+        )
+        .unwrap();
+        let output = run_ascii(program, "hi there\nignored").unwrap();
+        assert_eq!(output.lines(), vec!["HI THERE"]);
+        assert_eq!(output.values, vec![1000]);
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::iter;
+
+use crate::machine::ParameterMode;
+use crate::opcodes::{OpcodeInfo, OpcodeRegistry};
+use crate::Program;
+
+/// An error in assembly source, with the one-based line it occurred on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+enum Statement<'a> {
+    Instruction(OpcodeInfo, Vec<&'a str>),
+    Data(Vec<&'a str>),
+}
+
+impl Statement<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Statement::Instruction(info, _) => info.param_count() + 1,
+            Statement::Data(values) => values.len(),
+        }
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+fn split_list(text: &str) -> Vec<&str> {
+    if text.trim().is_empty() {
+        Vec::new()
+    } else {
+        text.split(',').map(|item| item.trim()).collect()
+    }
+}
+
+fn evaluate_term(term: &str, labels: &HashMap<&str, usize>) -> Result<i128, String> {
+    if let Ok(value) = term.parse() {
+        Ok(value)
+    } else if is_identifier(term) {
+        match labels.get(term) {
+            Some(address) => Ok(*address as i128),
+            None => Err(format!("Undefined label '{}'", term)),
+        }
+    } else {
+        Err(format!("'{}' is not a number or label", term))
+    }
+}
+
+/// Evaluates a sum of numbers and labels, e.g. `-3`, `loop` or `table+2`. Terms are summed in
+/// 128 bits, so only the result has to fit in an i64.
+fn evaluate(expr: &str, labels: &HashMap<&str, usize>) -> Result<i64, String> {
+    let out_of_range = || format!("'{}' is out of range for a 64-bit value", expr.trim());
+    let expr = expr.trim();
+    if expr.is_empty() {
+        return Err("Missing value".to_string());
+    }
+
+    let mut total: i128 = 0;
+    let mut sign = 1;
+    let mut start = 0;
+    // The trailing '+' flushes the last term
+    for (index, c) in expr.char_indices().chain(iter::once((expr.len(), '+'))) {
+        if c == '+' || c == '-' {
+            let term = expr[start..index].trim();
+            if !term.is_empty() {
+                let value = sign * evaluate_term(term, labels)?;
+                total = total.checked_add(value).ok_or_else(out_of_range)?;
+            } else if index != 0 {
+                return Err(format!("Malformed expression '{}'", expr));
+            }
+            sign = if c == '-' {
+                -1
+            } else {
+                1
+            };
+            start = index + 1;
+        }
+    }
+    i64::try_from(total).map_err(|_| out_of_range())
+}
+
+fn parse_operand(
+    operand: &str,
+    labels: &HashMap<&str, usize>,
+) -> Result<(ParameterMode, i64), String> {
+    if let Some(inner) = operand.strip_prefix('[') {
+        match inner.strip_suffix(']') {
+            Some(inner) => Ok((ParameterMode::Position, evaluate(inner, labels)?)),
+            None => Err(format!("Missing ']' in operand '{}'", operand)),
+        }
+    } else if let Some(value) = operand.strip_prefix('#') {
+        Ok((ParameterMode::Immediate, evaluate(value, labels)?))
+    } else if let Some(offset) = operand.strip_prefix("rb") {
+        let offset = offset.trim();
+        if offset.is_empty() {
+            Ok((ParameterMode::Relative, 0))
+        } else if offset.starts_with('+') || offset.starts_with('-') {
+            Ok((ParameterMode::Relative, evaluate(offset, labels)?))
+        } else {
+            Err(format!("Malformed relative operand '{}'", operand))
+        }
+    } else {
+        Err(format!("Operand '{}' needs an addressing mode: [addr], #imm or rb+off", operand))
+    }
+}
+
+fn parse_statement<'a>(text: &'a str, opcodes: &OpcodeRegistry) -> Result<Statement<'a>, String> {
+    let (head, rest) = match text.find(char::is_whitespace) {
+        Some(index) => (&text[..index], &text[index..]),
+        None => (text, ""),
+    };
+
+    if head == ".data" {
+        let values = split_list(rest);
+        if values.is_empty() {
+            return Err(".data needs at least one value".to_string());
+        }
+        return Ok(Statement::Data(values));
+    }
+
+    let info = match opcodes.code_for(head).and_then(|code| opcodes.info(code)) {
+        Some(info) => info,
+        None => return Err(format!("Unknown mnemonic '{}'", head)),
+    };
+    let operands = split_list(rest);
+    if operands.len() != info.param_count() {
+        return Err(format!(
+            "{} takes {} operands but was given {}",
+            info.mnemonic,
+            info.param_count(),
+            operands.len()
+        ));
+    }
+    Ok(Statement::Instruction(info, operands))
+}
+
+fn encode_instruction(
+    info: &OpcodeInfo,
+    operands: &[&str],
+    labels: &HashMap<&str, usize>,
+    program: &mut Program,
+) -> Result<(), String> {
+    let mut instruction = info.code as i64;
+    let mut values = Vec::new();
+    for (index, operand) in operands.iter().enumerate() {
+        let (mode, value) = parse_operand(operand, labels)?;
+        if mode == ParameterMode::Immediate && info.writes(index) {
+            return Err(format!(
+                "Operand {} of {} is written to and can't be immediate",
+                index + 1,
+                info.mnemonic
+            ));
+        }
+        let mode_digit = match mode {
+            ParameterMode::Position => 0,
+            ParameterMode::Immediate => 1,
+            ParameterMode::Relative => 2,
+        };
+        instruction += mode_digit * 10i64.pow(index as u32 + 2);
+        values.push(value);
+    }
+    program.push(instruction);
+    program.append(&mut values);
+    Ok(())
+}
+
+/// Assembles Intcode source into a program.
+///
+/// Each line holds an optional label (`name:`), then an instruction or a `.data` directive, then
+/// an optional `;` comment. Operands use the same syntax as the disassembler: `[addr]` for
+/// position mode, `#value` for immediate mode and `rb+offset` for relative mode, where any value
+/// can be a number, a label or a sum of them like `table+2`. A line may also start with a numeric
+/// address like `12:`, which must match the address the line is assembled at; this lets
+/// disassembler listings be assembled back into the original program. Mnemonics are looked up in
+/// the given opcodes, so custom instructions can be assembled too.
+pub fn assemble(source: &str, opcodes: &OpcodeRegistry) -> Result<Program, AsmError> {
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut address = 0;
+
+    for (line_index, line) in source.lines().enumerate() {
+        let error = |message| AsmError {
+            line: line_index + 1,
+            message,
+        };
+        let mut text = match line.find(';') {
+            Some(index) => &line[..index],
+            None => line,
+        }
+        .trim();
+
+        while let Some(colon) = text.find(':') {
+            let name = text[..colon].trim();
+            if let Ok(expected) = name.parse::<usize>() {
+                if expected != address {
+                    return Err(error(format!(
+                        "Line is marked as address {} but is at address {}",
+                        expected, address
+                    )));
+                }
+            } else if !is_identifier(name) {
+                return Err(error(format!("'{}' is not a valid label", name)));
+            } else if labels.insert(name, address).is_some() {
+                return Err(error(format!("Label '{}' is defined more than once", name)));
+            }
+            text = text[colon + 1..].trim();
+        }
+
+        if !text.is_empty() {
+            let statement = parse_statement(text, opcodes).map_err(error)?;
+            address += statement.len();
+            statements.push((line_index + 1, statement));
+        }
+    }
+
+    let mut program = Program::new();
+    for (line, statement) in statements {
+        let result = match statement {
+            Statement::Instruction(info, operands) => {
+                encode_instruction(&info, &operands, &labels, &mut program)
+            }
+            Statement::Data(values) => values.iter().try_for_each(|value| {
+                program.push(evaluate(value, &labels)?);
+                Ok(())
+            }),
+        };
+        result.map_err(|message| AsmError {
+            line,
+            message,
+        })?;
+    }
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{disassemble, parse_program};
+
+    fn assemble_standard(source: &str) -> Result<Program, AsmError> {
+        assemble(source, &OpcodeRegistry::standard())
+    }
+
+    #[test]
+    fn test_assemble_with_labels() {
+        let source = "
+            ; Count down from 3, printing each number
+            start:  OUT [counter]
+                    ADD [counter], #-1, [counter]
+                    JT [counter], #start
+                    HLT
+            counter: .data 3
+        ";
+        assert_eq!(
+            assemble_standard(source),
+            Ok(vec![4, 10, 1001, 10, -1, 10, 1005, 10, 0, 99, 3])
+        );
+        assert_eq!(assemble_standard(".data -9223372036854775808"), Ok(vec![i64::MIN]));
+    }
+
+    #[test]
+    fn test_round_trip_with_disassembler() {
+        let program =
+            parse_program("109,19,21101,5,-2,0,204,0,1206,-1,17,2,3,4,15,99,7,99,0").unwrap();
+        assert_eq!(
+            assemble_standard(&disassemble(&program, &OpcodeRegistry::standard())),
+            Ok(program)
+        );
+
+        // The extra mode digit on the output can't be written in assembly, but the listing still
+        // has to stay in step with the instructions that run after it
+        let program = parse_program("10104,5,1105,1,7,99,99,4,0,99").unwrap();
+        let listing = disassemble(&program, &OpcodeRegistry::standard());
+        assert_eq!(
+            listing.lines().take(3).collect::<Vec<_>>(),
+            vec!["    0: .data 10104, 5 ; OUT #5", "    2: JT #1, #7", "    5: HLT"]
+        );
+        assert_eq!(assemble_standard(&listing), Ok(program));
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        let error = |line, message: &str| {
+            Err(AsmError {
+                line,
+                message: message.to_string(),
+            })
+        };
+        assert_eq!(assemble_standard("NOP"), error(1, "Unknown mnemonic 'NOP'"));
+        assert_eq!(
+            assemble_standard("\nADD #1, #2"),
+            error(2, "ADD takes 3 operands but was given 2")
+        );
+        assert_eq!(
+            assemble_standard("IN #5"),
+            error(1, "Operand 1 of IN is written to and can't be immediate")
+        );
+        assert_eq!(assemble_standard("JT #1, #nowhere"), error(1, "Undefined label 'nowhere'"));
+        assert_eq!(
+            assemble_standard(".data 9223372036854775807+1"),
+            error(1, "'9223372036854775807+1' is out of range for a 64-bit value")
+        );
+        assert_eq!(
+            assemble_standard("HLT\n3: HLT"),
+            error(2, "Line is marked as address 3 but is at address 1")
+        );
+    }
+}
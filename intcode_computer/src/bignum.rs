@@ -0,0 +1,327 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::ops::{Add, Mul};
+use std::str::FromStr;
+
+use crate::word::Word;
+
+/// Numbers are printed and parsed this many decimal digits at a time.
+const DECIMAL_CHUNK: u32 = 1_000_000_000;
+const DECIMAL_CHUNK_DIGITS: usize = 9;
+
+/// An arbitrary-precision integer, for running programs whose values outgrow every fixed-size
+/// word.
+///
+/// Stored as a sign and a magnitude in base 2^32, least significant limb first, with no leading
+/// zero limbs. Zero has an empty magnitude and is never negative.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    magnitude: Vec<u32>,
+}
+
+/// Returned when parsing a string which isn't a decimal integer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseBigIntError;
+
+impl fmt::Display for ParseBigIntError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid digit found in string")
+    }
+}
+
+impl Error for ParseBigIntError {}
+
+fn trim(magnitude: &mut Vec<u32>) {
+    while magnitude.last() == Some(&0) {
+        magnitude.pop();
+    }
+}
+
+fn compare_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut sum = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+        let limb = u64::from(*a.get(i).unwrap_or(&0)) + u64::from(*b.get(i).unwrap_or(&0)) + carry;
+        sum.push(limb as u32);
+        carry = limb >> 32;
+    }
+    sum.push(carry as u32);
+    trim(&mut sum);
+    sum
+}
+
+/// Subtracts b from a, which must be at least as large.
+fn subtract_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut difference = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, &limb) in a.iter().enumerate() {
+        let subtrahend = i64::from(*b.get(i).unwrap_or(&0)) + borrow;
+        let mut limb = i64::from(limb) - subtrahend;
+        borrow = 0;
+        if limb < 0 {
+            limb += 1 << 32;
+            borrow = 1;
+        }
+        difference.push(limb as u32);
+    }
+    trim(&mut difference);
+    difference
+}
+
+fn multiply_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut product = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0;
+        for (j, &y) in b.iter().enumerate() {
+            let limb = u64::from(x) * u64::from(y) + u64::from(product[i + j]) + carry;
+            product[i + j] = limb as u32;
+            carry = limb >> 32;
+        }
+        product[i + b.len()] = carry as u32;
+    }
+    trim(&mut product);
+    product
+}
+
+impl BigInt {
+    fn from_parts(negative: bool, magnitude: Vec<u32>) -> BigInt {
+        BigInt {
+            negative: negative && !magnitude.is_empty(),
+            magnitude,
+        }
+    }
+
+    /// The magnitude as a u64, if it fits in one.
+    fn magnitude_u64(&self) -> Option<u64> {
+        match self.magnitude.as_slice() {
+            [] => Some(0),
+            [low] => Some(u64::from(*low)),
+            [low, high] => Some(u64::from(*high) << 32 | u64::from(*low)),
+            _ => None,
+        }
+    }
+
+    /// Divides the magnitude in place, returning the remainder.
+    fn divide_magnitude(&mut self, divisor: u32) -> u32 {
+        let mut remainder = 0;
+        for limb in self.magnitude.iter_mut().rev() {
+            let value = u64::from(remainder) << 32 | u64::from(*limb);
+            *limb = (value / u64::from(divisor)) as u32;
+            remainder = (value % u64::from(divisor)) as u32;
+        }
+        trim(&mut self.magnitude);
+        remainder
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(value: i64) -> Self {
+        let magnitude = value.unsigned_abs();
+        let mut limbs = vec![magnitude as u32, (magnitude >> 32) as u32];
+        trim(&mut limbs);
+        BigInt::from_parts(value < 0, limbs)
+    }
+}
+
+impl Add for BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::from_parts(
+                self.negative,
+                add_magnitudes(&self.magnitude, &other.magnitude),
+            );
+        }
+        // The signs differ, so the result takes the sign of whichever has the larger magnitude
+        match compare_magnitudes(&self.magnitude, &other.magnitude) {
+            Ordering::Less => BigInt::from_parts(
+                other.negative,
+                subtract_magnitudes(&other.magnitude, &self.magnitude),
+            ),
+            _ => BigInt::from_parts(
+                self.negative,
+                subtract_magnitudes(&self.magnitude, &other.magnitude),
+            ),
+        }
+    }
+}
+
+impl Mul for BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: BigInt) -> BigInt {
+        BigInt::from_parts(
+            self.negative != other.negative,
+            multiply_magnitudes(&self.magnitude, &other.magnitude),
+        )
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare_magnitudes(&self.magnitude, &other.magnitude),
+            (true, true) => compare_magnitudes(&other.magnitude, &self.magnitude),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut remaining = self.clone();
+        let mut chunks = Vec::new();
+        loop {
+            chunks.push(remaining.divide_magnitude(DECIMAL_CHUNK));
+            if remaining.magnitude.is_empty() {
+                break;
+            }
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        let mut chunks = chunks.iter().rev();
+        write!(f, "{}", chunks.next().unwrap())?;
+        for chunk in chunks {
+            write!(f, "{:0width$}", chunk, width = DECIMAL_CHUNK_DIGITS)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for BigInt {
+    type Err = ParseBigIntError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        if digits.is_empty() || !digits.bytes().all(|digit| digit.is_ascii_digit()) {
+            return Err(ParseBigIntError);
+        }
+
+        let mut magnitude = Vec::new();
+        for digit in digits.bytes() {
+            // magnitude = magnitude * 10 + digit
+            let mut carry = u64::from(digit - b'0');
+            for limb in magnitude.iter_mut() {
+                let value = u64::from(*limb) * 10 + carry;
+                *limb = value as u32;
+                carry = value >> 32;
+            }
+            if carry != 0 {
+                magnitude.push(carry as u32);
+            }
+        }
+        Ok(BigInt::from_parts(negative, magnitude))
+    }
+}
+
+impl Word for BigInt {
+    fn from_i64(value: i64) -> Self {
+        BigInt::from(value)
+    }
+
+    fn from_u128(value: u128) -> Option<Self> {
+        let mut limbs: Vec<u32> = (0..4).map(|limb| (value >> (32 * limb)) as u32).collect();
+        trim(&mut limbs);
+        Some(BigInt::from_parts(false, limbs))
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        let magnitude = self.magnitude_u64()?;
+        if self.negative {
+            i64::try_from(-i128::from(magnitude)).ok()
+        } else {
+            i64::try_from(magnitude).ok()
+        }
+    }
+
+    fn to_usize(&self) -> Option<usize> {
+        if self.negative {
+            None
+        } else {
+            usize::try_from(self.magnitude_u64()?).ok()
+        }
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self.clone() + other.clone())
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self.clone() * other.clone())
+    }
+
+    fn wrapping_add(&self, other: &Self) -> Self {
+        self.clone() + other.clone()
+    }
+
+    fn wrapping_mul(&self, other: &Self) -> Self {
+        self.clone() * other.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(text: &str) -> BigInt {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        for text in &["0", "-1", "4294967296", "-123456789012345678901234567890"] {
+            assert_eq!(big(text).to_string(), *text);
+        }
+        assert_eq!(big("+007").to_string(), "7");
+        assert_eq!(big("-0"), BigInt::default());
+        assert_eq!("12a".parse::<BigInt>(), Err(ParseBigIntError));
+        assert_eq!("-".parse::<BigInt>(), Err(ParseBigIntError));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = big("123456789012345678901234567890");
+        let b = big("-987654321098765432109876543210");
+        assert_eq!(a.clone() + b.clone(), big("-864197532086419753208641975320"));
+        assert_eq!(b.clone() + a.clone(), big("-864197532086419753208641975320"));
+        assert_eq!(a.clone() + big("-123456789012345678901234567890"), BigInt::default());
+        assert_eq!(
+            a.clone() * b.clone(),
+            big("-121932631137021795226185032733622923332237463801111263526900")
+        );
+        assert!(b < a);
+        assert!(big("-5") < big("-4"));
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(BigInt::from_i64(i64::MIN).to_i64(), Some(i64::MIN));
+        assert_eq!(BigInt::from_i64(i64::MAX).to_i64(), Some(i64::MAX));
+        assert_eq!(big("9223372036854775808").to_i64(), None);
+        assert_eq!(big("-3").to_usize(), None);
+        assert_eq!(big("42").to_usize(), Some(42));
+        assert_eq!(
+            BigInt::from_u128(u128::MAX),
+            Some(big("340282366920938463463374607431768211455"))
+        );
+    }
+}
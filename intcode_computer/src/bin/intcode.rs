@@ -0,0 +1,230 @@
+extern crate intcode_computer;
+
+use intcode_computer::{
+    BinaryTracer, Coverage, Machine, OpcodeRegistry, PrintTracer, Profile, Profiler,
+    SelfModificationDetector, Tracer,
+};
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::process;
+use std::rc::Rc;
+
+const USAGE: &str = "\
+Usage: intcode <program file> [--disasm | --validate | --cfg] [--ascii] [--strict]
+                [--profile <name>] [--trace] [--trace-file <file>] [--hotspots <file>]
+                [--flamegraph <file>] [--coverage <file>] [--lcov <file>] [--self-modifying]
+       intcode <assembly file> --asm
+
+  --disasm             Print a disassembly listing instead of running the program
+  --validate           Print the profile the program needs instead of running it
+  --cfg                Print the program's control flow graph in Graphviz's DOT language
+  --asm                Assemble the file and print the program
+  --ascii              Talk to the program in ASCII text rather than one integer per line
+  --strict             Fail on arithmetic overflow and jumps outside the program
+  --profile <name>     Only allow the instructions in a profile: day2, day5, day9 or full
+  --trace              Print each instruction to stderr as it runs
+  --trace-file <file>  Write a binary trace of the run to file
+  --hotspots <file>    Profile the run and write a report of the hottest code to file
+  --flamegraph <file>  Profile the run and write folded stacks for a flamegraph to file
+  --coverage <file>    Write a listing of the program annotated with what the run covered
+  --lcov <file>        Write the run's coverage to file in lcov's format
+  --self-modifying     Report to stderr where the program could and did modify its own code";
+
+/// How many addresses and jumps to list in a hotspots report.
+const HOTSPOTS: usize = 20;
+
+#[derive(Default)]
+struct Options {
+    path: String,
+    disasm: bool,
+    validate: bool,
+    cfg: bool,
+    asm: bool,
+    ascii: bool,
+    strict: bool,
+    profile: Option<Profile>,
+    trace: bool,
+    trace_file: Option<String>,
+    hotspots: Option<String>,
+    flamegraph: Option<String>,
+    coverage: Option<String>,
+    lcov: Option<String>,
+    self_modifying: bool,
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn parse_args() -> Options {
+    let mut options = Options::default();
+    let mut path = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--disasm" => options.disasm = true,
+            "--validate" => options.validate = true,
+            "--cfg" => options.cfg = true,
+            "--asm" => options.asm = true,
+            "--ascii" => options.ascii = true,
+            "--strict" => options.strict = true,
+            "--profile" => {
+                let name = args.next().unwrap_or_else(|| usage());
+                match name.parse() {
+                    Ok(profile) => options.profile = Some(profile),
+                    Err(why) => {
+                        eprintln!("{}", why);
+                        usage();
+                    }
+                }
+            }
+            "--trace" => options.trace = true,
+            "--trace-file" => options.trace_file = Some(args.next().unwrap_or_else(|| usage())),
+            "--hotspots" => options.hotspots = Some(args.next().unwrap_or_else(|| usage())),
+            "--flamegraph" => options.flamegraph = Some(args.next().unwrap_or_else(|| usage())),
+            "--coverage" => options.coverage = Some(args.next().unwrap_or_else(|| usage())),
+            "--lcov" => options.lcov = Some(args.next().unwrap_or_else(|| usage())),
+            "--self-modifying" => options.self_modifying = true,
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => usage(),
+        }
+    }
+    options.path = path.unwrap_or_else(|| usage());
+    options
+}
+
+fn main() {
+    let options = parse_args();
+    let path = &options.path;
+
+    let opcodes = OpcodeRegistry::standard();
+    let contents = match fs::read_to_string(path) {
+        Err(why) => panic!("couldn't open {}: {}", path, why),
+        Ok(contents) => contents,
+    };
+
+    if options.asm {
+        match intcode_computer::assemble(&contents, &opcodes) {
+            Err(why) => panic!("couldn't assemble {}: {}", path, why),
+            Ok(program) => {
+                let cells: Vec<String> = program.iter().map(|value| value.to_string()).collect();
+                println!("{}", cells.join(","));
+            }
+        }
+        return;
+    }
+
+    let program = match intcode_computer::parse_program(&contents) {
+        Err(why) => panic!("couldn't parse {}: {}", path, why),
+        Ok(program) => program,
+    };
+
+    if options.disasm {
+        print!("{}", intcode_computer::disassemble(&program, &opcodes));
+        return;
+    }
+
+    if options.cfg {
+        let graph = intcode_computer::control_flow_graph(&program, &opcodes);
+        print!("{}", graph.to_dot(&program, &opcodes));
+        return;
+    }
+
+    if options.validate {
+        let validation = intcode_computer::validate_program(&program, &opcodes);
+        match validation.needed_by {
+            Some(address) => {
+                println!("Needs the {} profile, first at address {}", validation.profile, address)
+            }
+            None => println!("Needs the {} profile", validation.profile),
+        }
+        for address in validation.invalid {
+            println!("Address {} is reachable but isn't a valid instruction", address);
+        }
+        for address in validation.unresolved_jumps {
+            println!("Jump at address {} has a target which can't be checked", address);
+        }
+        return;
+    }
+
+    let mut machine = Machine::new(program.clone());
+    machine.set_strict(options.strict);
+    if let Some(profile) = options.profile {
+        machine.set_profile(profile);
+    }
+    let profiler = Rc::new(RefCell::new(Profiler::new()));
+    let profiling = options.hotspots.is_some() || options.flamegraph.is_some();
+    let coverage = Rc::new(RefCell::new(Coverage::new()));
+    let detector = Rc::new(RefCell::new(SelfModificationDetector::new()));
+    let covering = options.coverage.is_some() || options.lcov.is_some();
+    let mut tracers: Vec<Box<dyn Tracer>> = Vec::new();
+    if profiling {
+        tracers.push(Box::new(Rc::clone(&profiler)));
+    }
+    if covering {
+        tracers.push(Box::new(Rc::clone(&coverage)));
+    }
+    if options.self_modifying {
+        for warning in intcode_computer::check_predecode(&program, &opcodes) {
+            eprintln!("Warning: {}", warning);
+        }
+        tracers.push(Box::new(Rc::clone(&detector)));
+    }
+    if options.trace {
+        tracers.push(Box::new(PrintTracer::new(io::stderr())));
+    }
+    if let Some(trace_path) = &options.trace_file {
+        match File::create(trace_path) {
+            Err(why) => panic!("couldn't create {}: {}", trace_path, why),
+            Ok(file) => tracers.push(Box::new(BinaryTracer::new(BufWriter::new(file)))),
+        }
+    }
+    if !tracers.is_empty() {
+        machine.set_tracer(Box::new(tracers));
+    }
+    let result = if options.ascii {
+        intcode_computer::run_machine_ascii_terminal(&mut machine)
+    } else {
+        intcode_computer::run_machine(&mut machine)
+    };
+    if let Err(why) = result {
+        panic!("Intcode program failed: {}", why);
+    }
+
+    let profiler = profiler.borrow();
+    if let Some(report_path) = &options.hotspots {
+        if let Err(why) = fs::write(report_path, profiler.report(HOTSPOTS)) {
+            panic!("couldn't write {}: {}", report_path, why);
+        }
+    }
+    if let Some(folded_path) = &options.flamegraph {
+        let written =
+            File::create(folded_path).and_then(|file| profiler.write_folded(BufWriter::new(file)));
+        if let Err(why) = written {
+            panic!("couldn't write {}: {}", folded_path, why);
+        }
+    }
+
+    let coverage = coverage.borrow();
+    if let Some(listing_path) = &options.coverage {
+        if let Err(why) = fs::write(listing_path, coverage.listing(&program, &opcodes)) {
+            panic!("couldn't write {}: {}", listing_path, why);
+        }
+    }
+    if let Some(lcov_path) = &options.lcov {
+        let written = File::create(lcov_path)
+            .and_then(|file| coverage.write_lcov(&program, &opcodes, path, BufWriter::new(file)));
+        if let Err(why) = written {
+            panic!("couldn't write {}: {}", lcov_path, why);
+        }
+    }
+
+    for write in detector.borrow().reports() {
+        eprintln!("{}", write);
+    }
+}
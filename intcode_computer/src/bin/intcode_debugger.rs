@@ -0,0 +1,487 @@
+extern crate intcode_computer;
+
+use intcode_computer::{Access, Event, Machine, OpcodeRegistry, WatchAction};
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::ops::RangeInclusive;
+use std::process;
+
+const USAGE: &str = "Usage: intcode_debugger <program file> [--input <values>]";
+
+/// How many instructions the debugger can step back through.
+const HISTORY_STEPS: usize = 1_000_000;
+
+const HELP: &str = "\
+Commands:
+  s, step [n]            Execute n instructions (default 1)
+  c, continue            Run until a breakpoint, input request, error or halt
+  rs, rstep [n]          Undo the last n instructions (default 1)
+  rc, rcontinue          Run backwards until a breakpoint or the start of the history
+  who <addr>             Show which instruction last wrote to addr
+  b, break <addr>        Break when the instruction pointer reaches addr
+  b, break op <op>       Break before any instruction with the given mnemonic or opcode
+  d, delete <addr>       Remove an address breakpoint
+  d, delete op <op>      Remove an opcode breakpoint
+  w, watch <addr>[-<end>] [read|write|change]
+                         Stop after an instruction accesses addr, or any address up to end
+                         (default: write)
+  unwatch <id>           Remove a watchpoint
+  breakpoints            List breakpoints and watchpoints
+  x, peek <addr> [n]     Show n memory cells starting at addr (default 1)
+  poke <addr> <value>    Set a memory cell
+  l, list [addr] [n]     Disassemble n instructions from addr (default: the current one)
+  r, regs                Show the instruction pointer, relative base and pending input
+  i, input <values>      Queue input values, separated by spaces or commas
+  h, help                Show this message
+  q, quit                Exit the debugger";
+
+#[derive(Debug, PartialEq)]
+enum Breakpoint {
+    Address(usize),
+    Opcode(u32),
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Step(usize),
+    Continue,
+    StepBack(usize),
+    ReverseContinue,
+    LastWrite(usize),
+    Break(Breakpoint),
+    Delete(Breakpoint),
+    Watch(RangeInclusive<usize>, Access),
+    Unwatch(usize),
+    ListBreakpoints,
+    Peek(usize, usize),
+    Poke(usize, i64),
+    List(Option<usize>, usize),
+    Registers,
+    Input(Vec<i64>),
+    Help,
+    Quit,
+}
+
+fn parse_number<T: std::str::FromStr>(text: Option<&str>, what: &str) -> Result<T, String> {
+    match text {
+        Some(text) => text.parse().map_err(|_| format!("'{}' is not a valid {}", text, what)),
+        None => Err(format!("Missing {}", what)),
+    }
+}
+
+fn parse_opcode(text: Option<&str>) -> Result<u32, String> {
+    let text = text.ok_or("Missing opcode")?;
+    match text.parse() {
+        Ok(code) => Ok(code),
+        Err(_) => OpcodeRegistry::<i64>::standard()
+            .code_for(text)
+            .ok_or(format!("'{}' is not a mnemonic or opcode", text)),
+    }
+}
+
+fn parse_breakpoint(args: &[&str]) -> Result<Breakpoint, String> {
+    match args.first() {
+        Some(&"op") => Ok(Breakpoint::Opcode(parse_opcode(args.get(1).copied())?)),
+        first => Ok(Breakpoint::Address(parse_number(first.copied(), "address")?)),
+    }
+}
+
+fn parse_watchpoint(args: &[&str]) -> Result<Command, String> {
+    let addresses = args.first().copied().ok_or("Missing address")?;
+    let addresses = match addresses.split_once('-') {
+        Some((start, end)) => {
+            parse_number(Some(start), "address")?..=parse_number(Some(end), "address")?
+        }
+        None => {
+            let address = parse_number(Some(addresses), "address")?;
+            address..=address
+        }
+    };
+    let access = match args.get(1).copied() {
+        Some("r") | Some("read") => Access::Read,
+        None | Some("w") | Some("write") => Access::Write,
+        Some("c") | Some("change") => Access::Change,
+        Some(access) => return Err(format!("'{}' is not read, write or change", access)),
+    };
+    Ok(Command::Watch(addresses, access))
+}
+
+fn parse_command(line: &str) -> Result<Command, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let (name, args) = match words.split_first() {
+        Some((name, args)) => (*name, args),
+        // An empty line repeats a single step, like most debuggers
+        None => return Ok(Command::Step(1)),
+    };
+    let arg = |index: usize| args.get(index).copied();
+
+    match name {
+        "s" | "step" => match arg(0) {
+            Some(_) => Ok(Command::Step(parse_number(arg(0), "count")?)),
+            None => Ok(Command::Step(1)),
+        },
+        "c" | "continue" => Ok(Command::Continue),
+        "rs" | "rstep" => match arg(0) {
+            Some(_) => Ok(Command::StepBack(parse_number(arg(0), "count")?)),
+            None => Ok(Command::StepBack(1)),
+        },
+        "rc" | "rcontinue" => Ok(Command::ReverseContinue),
+        "who" => Ok(Command::LastWrite(parse_number(arg(0), "address")?)),
+        "b" | "break" => Ok(Command::Break(parse_breakpoint(args)?)),
+        "d" | "delete" => Ok(Command::Delete(parse_breakpoint(args)?)),
+        "w" | "watch" => parse_watchpoint(args),
+        "unwatch" => Ok(Command::Unwatch(parse_number(arg(0), "watchpoint id")?)),
+        "breakpoints" => Ok(Command::ListBreakpoints),
+        "x" | "peek" => {
+            let count = if arg(1).is_some() {
+                parse_number(arg(1), "count")?
+            } else {
+                1
+            };
+            Ok(Command::Peek(parse_number(arg(0), "address")?, count))
+        }
+        "poke" => {
+            Ok(Command::Poke(parse_number(arg(0), "address")?, parse_number(arg(1), "value")?))
+        }
+        "l" | "list" => {
+            let address = if arg(0).is_some() {
+                Some(parse_number(arg(0), "address")?)
+            } else {
+                None
+            };
+            let count = if arg(1).is_some() {
+                parse_number(arg(1), "count")?
+            } else {
+                1
+            };
+            Ok(Command::List(address, count))
+        }
+        "r" | "regs" => Ok(Command::Registers),
+        "i" | "input" => {
+            let values = args
+                .iter()
+                .flat_map(|arg| arg.split(','))
+                .filter(|value| !value.is_empty())
+                .map(|value| parse_number(Some(value), "input value"))
+                .collect::<Result<Vec<i64>, String>>()?;
+            if values.is_empty() {
+                return Err("Missing input values".to_string());
+            }
+            Ok(Command::Input(values))
+        }
+        "h" | "help" => Ok(Command::Help),
+        "q" | "quit" => Ok(Command::Quit),
+        _ => Err(format!("Unknown command '{}', try 'help'", name)),
+    }
+}
+
+fn is_breakpoint(
+    machine: &Machine,
+    address_breakpoints: &HashSet<usize>,
+    opcode_breakpoints: &HashSet<u32>,
+) -> bool {
+    let ip = machine.ip();
+    address_breakpoints.contains(&ip)
+        || opcode_breakpoints.contains(&((machine.read(ip) % 100) as u32))
+}
+
+struct Debugger {
+    machine: Machine,
+    address_breakpoints: HashSet<usize>,
+    opcode_breakpoints: HashSet<u32>,
+    watchpoints: Vec<(usize, RangeInclusive<usize>, Access)>,
+    halted: bool,
+}
+
+impl Debugger {
+    fn describe_instruction(&self, address: usize) -> (String, usize) {
+        // Fewer cells at the very end of memory, so a long instruction there shows up as data
+        let cells: Vec<i64> =
+            (address..=address.saturating_add(3)).map(|a| self.machine.read(a)).collect();
+        match intcode_computer::disassemble_instruction(&cells, 0, self.machine.opcodes()) {
+            Some(instruction) => instruction,
+            None => (format!(".data {}", cells[0]), 1),
+        }
+    }
+
+    fn print_current(&self) {
+        let (text, _) = self.describe_instruction(self.machine.ip());
+        println!("{:5}: {}", self.machine.ip(), text);
+    }
+
+    fn at_breakpoint(&self) -> bool {
+        is_breakpoint(&self.machine, &self.address_breakpoints, &self.opcode_breakpoints)
+    }
+
+    /// Executes one instruction, reporting anything interesting that happened. Returns false if
+    /// the machine can't make progress.
+    fn step(&mut self) -> bool {
+        if self.halted {
+            println!("Program has halted.");
+            return false;
+        }
+        match self.machine.step() {
+            Ok(None) => true,
+            Ok(Some(Event::Output(value))) => {
+                println!("Output: {}", value);
+                true
+            }
+            Ok(Some(Event::NeedsInput)) => {
+                println!("Program needs input, queue some with 'input <values>'.");
+                false
+            }
+            Ok(Some(Event::Halted)) => {
+                println!("Program halted.");
+                self.halted = true;
+                false
+            }
+            Ok(Some(Event::Watchpoint(hit))) => {
+                match hit.access {
+                    Access::Read => println!(
+                        "Watchpoint {}: instruction at {} read {} from {}",
+                        hit.id, hit.ip, hit.new_value, hit.address
+                    ),
+                    _ => println!(
+                        "Watchpoint {}: instruction at {} wrote {} to {} (was {})",
+                        hit.id, hit.ip, hit.new_value, hit.address, hit.old_value
+                    ),
+                }
+                false
+            }
+            Err(why) => {
+                println!("Error: {}", why);
+                false
+            }
+        }
+    }
+
+    fn execute(&mut self, command: Command) -> bool {
+        match command {
+            Command::Step(count) => {
+                for _ in 0..count {
+                    if !self.step() {
+                        break;
+                    }
+                }
+                self.print_current();
+            }
+            Command::Continue => {
+                while self.step() {
+                    if self.at_breakpoint() {
+                        println!("Breakpoint hit.");
+                        break;
+                    }
+                }
+                self.print_current();
+            }
+            Command::StepBack(count) => {
+                for _ in 0..count {
+                    if !self.machine.step_back() {
+                        println!("Reached the start of the history.");
+                        break;
+                    }
+                    self.halted = false;
+                }
+                self.print_current();
+            }
+            Command::ReverseContinue => {
+                let addresses = &self.address_breakpoints;
+                let codes = &self.opcode_breakpoints;
+                let start = self.machine.history_len();
+                if self.machine.reverse_continue(|machine| is_breakpoint(machine, addresses, codes))
+                {
+                    println!("Breakpoint hit.");
+                } else {
+                    println!("Reached the start of the history.");
+                }
+                if self.machine.history_len() < start {
+                    self.halted = false;
+                }
+                self.print_current();
+            }
+            Command::LastWrite(address) => match self.machine.last_write(address) {
+                Some(write) => {
+                    let (text, _) = self.describe_instruction(write.ip);
+                    println!(
+                        "{} was changed from {} to {} by {}: {}",
+                        address, write.old_value, write.new_value, write.ip, text
+                    );
+                }
+                None => println!("{} hasn't been written to in the history", address),
+            },
+            Command::Break(Breakpoint::Address(address)) => {
+                self.address_breakpoints.insert(address);
+            }
+            Command::Break(Breakpoint::Opcode(code)) => {
+                self.opcode_breakpoints.insert(code);
+            }
+            Command::Delete(Breakpoint::Address(address)) => {
+                if !self.address_breakpoints.remove(&address) {
+                    println!("No breakpoint at address {}", address);
+                }
+            }
+            Command::Delete(Breakpoint::Opcode(code)) => {
+                if !self.opcode_breakpoints.remove(&code) {
+                    println!("No breakpoint on opcode {}", code);
+                }
+            }
+            Command::Watch(addresses, access) => {
+                let id = self.machine.add_watchpoint(addresses.clone(), access, WatchAction::Pause);
+                println!("Watchpoint {} set", id);
+                self.watchpoints.push((id, addresses, access));
+            }
+            Command::Unwatch(id) => {
+                if self.machine.remove_watchpoint(id) {
+                    self.watchpoints.retain(|(other, _, _)| *other != id);
+                } else {
+                    println!("No watchpoint {}", id);
+                }
+            }
+            Command::ListBreakpoints => {
+                let mut addresses: Vec<_> = self.address_breakpoints.iter().collect();
+                addresses.sort();
+                for address in addresses {
+                    println!("Address {}", address);
+                }
+                let mut codes: Vec<_> = self.opcode_breakpoints.iter().collect();
+                codes.sort();
+                for code in codes {
+                    println!("Opcode {}", code);
+                }
+                for (id, addresses, access) in &self.watchpoints {
+                    println!(
+                        "Watchpoint {}: {:?} of {}-{}",
+                        id,
+                        access,
+                        addresses.start(),
+                        addresses.end()
+                    );
+                }
+            }
+            Command::Peek(address, count) => {
+                for address in (address..=usize::MAX).take(count) {
+                    println!("{:5}: {}", address, self.machine.read(address));
+                }
+            }
+            Command::Poke(address, value) => self.machine.write(address, value),
+            Command::List(address, count) => {
+                let mut address = address.unwrap_or_else(|| self.machine.ip());
+                for _ in 0..count {
+                    let (text, len) = self.describe_instruction(address);
+                    let marker = if address == self.machine.ip() {
+                        '>'
+                    } else {
+                        ' '
+                    };
+                    println!("{}{:5}: {}", marker, address, text);
+                    address = match address.checked_add(len) {
+                        Some(next) => next,
+                        None => break,
+                    };
+                }
+            }
+            Command::Registers => {
+                println!("IP: {}", self.machine.ip());
+                println!("Relative base: {}", self.machine.relative_base());
+                println!("Pending input: {:?}", self.machine.pending_inputs());
+            }
+            Command::Input(values) => {
+                for value in values {
+                    self.machine.push_input(value);
+                }
+            }
+            Command::Help => println!("{}", HELP),
+            Command::Quit => return false,
+        }
+        true
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (path, inputs) = match args.as_slice() {
+        [path] => (path, None),
+        [path, flag, inputs] if flag == "--input" => (path, Some(inputs)),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    let contents = match fs::read_to_string(path) {
+        Err(why) => panic!("couldn't open {}: {}", path, why),
+        Ok(contents) => contents,
+    };
+    let program = match intcode_computer::parse_program(&contents) {
+        Err(why) => panic!("couldn't parse {}: {}", path, why),
+        Ok(program) => program,
+    };
+
+    let mut machine = Machine::new(program);
+    machine.record_history(HISTORY_STEPS);
+    let mut debugger = Debugger {
+        machine,
+        address_breakpoints: HashSet::new(),
+        opcode_breakpoints: HashSet::new(),
+        watchpoints: Vec::new(),
+        halted: false,
+    };
+    if let Some(inputs) = inputs {
+        match parse_command(&format!("input {}", inputs)) {
+            Ok(command) => {
+                debugger.execute(command);
+            }
+            Err(why) => panic!("bad --input: {}", why),
+        }
+    }
+
+    println!("Type 'help' for a list of commands.");
+    debugger.print_current();
+    let stdin = io::stdin();
+    loop {
+        print!("(icdb) ");
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+        match parse_command(&line) {
+            Ok(command) => {
+                if !debugger.execute(command) {
+                    break;
+                }
+            }
+            Err(why) => println!("{}", why),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command(""), Ok(Command::Step(1)));
+        assert_eq!(parse_command("step 10"), Ok(Command::Step(10)));
+        assert_eq!(parse_command("rs"), Ok(Command::StepBack(1)));
+        assert_eq!(parse_command("who 12"), Ok(Command::LastWrite(12)));
+        assert_eq!(parse_command("b 42"), Ok(Command::Break(Breakpoint::Address(42))));
+        assert_eq!(parse_command("break op out"), Ok(Command::Break(Breakpoint::Opcode(4))));
+        assert_eq!(parse_command("d op 99"), Ok(Command::Delete(Breakpoint::Opcode(99))));
+        assert_eq!(parse_command("x 100 4"), Ok(Command::Peek(100, 4)));
+        assert_eq!(parse_command("poke 7 -1"), Ok(Command::Poke(7, -1)));
+        assert_eq!(parse_command("input 1, 2 3"), Ok(Command::Input(vec![1, 2, 3])));
+        assert_eq!(parse_command("w 12"), Ok(Command::Watch(12..=12, Access::Write)));
+        assert_eq!(
+            parse_command("watch 10-19 change"),
+            Ok(Command::Watch(10..=19, Access::Change))
+        );
+        assert!(parse_command("watch 10 often").is_err());
+        assert!(parse_command("poke 7").is_err());
+        assert!(parse_command("frobnicate").is_err());
+    }
+}
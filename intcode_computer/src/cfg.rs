@@ -0,0 +1,285 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::convert::TryFrom;
+use std::fmt::Write as _;
+
+use crate::disasm::disassemble_instruction;
+use crate::machine::{Opcode, ParameterMode};
+use crate::opcodes::{ControlFlow, OpcodeInfo, OpcodeRegistry};
+
+/// Decodes the instruction at an address, if it's a valid one which fits in the program.
+pub(crate) fn decode(
+    program: &[i64],
+    address: usize,
+    opcodes: &OpcodeRegistry,
+) -> Option<(Opcode, OpcodeInfo)> {
+    let opcode = Opcode::from_value(*program.get(address)?)?;
+    let info = opcodes.info(opcode.code)?;
+    if address + info.param_count() < program.len() {
+        Some((opcode, info))
+    } else {
+        None
+    }
+}
+
+/// Where control can go after an instruction, as far as can be told without running it.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Flow {
+    pub(crate) successors: Vec<usize>,
+    /// The instruction is a jump whose target is read from memory.
+    pub(crate) indirect: bool,
+}
+
+pub(crate) fn flow(program: &[i64], address: usize, opcode: &Opcode, info: &OpcodeInfo) -> Flow {
+    let next = address + 1 + info.param_count();
+    let mut flow = Flow {
+        successors: Vec::new(),
+        indirect: false,
+    };
+    match info.flow {
+        ControlFlow::Halt => (),
+        ControlFlow::JumpIfTrue | ControlFlow::JumpIfFalse => {
+            // A constant condition means only one way out is possible
+            let condition = program[address + 1] != 0;
+            let constant = opcode.param_1_mode == ParameterMode::Immediate;
+            let jumps_if = info.flow == ControlFlow::JumpIfTrue;
+            if !constant || condition != jumps_if {
+                flow.successors.push(next);
+            }
+            if !constant || condition == jumps_if {
+                match (opcode.param_2_mode, usize::try_from(program[address + 2])) {
+                    (ParameterMode::Immediate, Ok(target)) => flow.successors.push(target),
+                    _ => flow.indirect = true,
+                }
+            }
+        }
+        ControlFlow::Next => flow.successors.push(next),
+        ControlFlow::Unknown => {
+            flow.successors.push(next);
+            flow.indirect = true;
+        }
+    }
+    flow
+}
+
+/// A straight run of instructions which is only ever entered at the top and left at the bottom.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    pub start: usize,
+    /// The address just after the block's last instruction.
+    pub end: usize,
+    /// The address of each instruction in the block.
+    pub instructions: Vec<usize>,
+    /// The addresses control can go to from the end of the block.
+    pub successors: Vec<usize>,
+    /// The block ends in a jump whose target isn't known until the program runs.
+    pub indirect_jump: bool,
+}
+
+/// The basic blocks of a program reachable from address 0, found without running it.
+///
+/// Code only reached through indirect jumps, or after the program has modified itself, can't be
+/// found this way.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControlFlowGraph {
+    /// Sorted by start address.
+    pub blocks: Vec<BasicBlock>,
+    /// Addresses which are reached but don't hold a valid instruction.
+    pub invalid: Vec<usize>,
+}
+
+/// Splits the reachable part of a program into basic blocks.
+pub fn control_flow_graph(program: &[i64], opcodes: &OpcodeRegistry) -> ControlFlowGraph {
+    let mut instructions = BTreeMap::new();
+    let mut invalid = BTreeSet::new();
+    let mut pending = vec![0];
+    while let Some(address) = pending.pop() {
+        if address >= program.len() || instructions.contains_key(&address) {
+            continue;
+        }
+        match decode(program, address, opcodes) {
+            Some((opcode, info)) => {
+                let flow = flow(program, address, &opcode, &info);
+                pending.extend(&flow.successors);
+                instructions.insert(address, (info.param_count() + 1, flow));
+            }
+            None => {
+                invalid.insert(address);
+            }
+        }
+    }
+
+    // A block starts at the entry point and wherever control arrives other than by falling
+    // through from the instruction before
+    let mut leaders: HashSet<usize> = HashSet::new();
+    leaders.insert(0);
+    for (&address, (len, flow)) in &instructions {
+        if flow.indirect || flow.successors != [address + len] {
+            leaders.extend(&flow.successors);
+        }
+    }
+
+    let mut blocks = Vec::new();
+    let mut starts: Vec<usize> = leaders.iter().copied().collect();
+    starts.sort_unstable();
+    for start in starts {
+        let mut block = BasicBlock {
+            start,
+            end: start,
+            instructions: Vec::new(),
+            successors: Vec::new(),
+            indirect_jump: false,
+        };
+        let mut address = start;
+        while let Some((len, flow)) = instructions.get(&address) {
+            block.instructions.push(address);
+            block.end = address + len;
+            block.successors = flow.successors.clone();
+            block.indirect_jump = flow.indirect;
+            address += len;
+            if flow.indirect || flow.successors != [address] || leaders.contains(&address) {
+                break;
+            }
+        }
+        if !block.instructions.is_empty() {
+            blocks.push(block);
+        }
+    }
+
+    ControlFlowGraph {
+        blocks,
+        invalid: invalid.into_iter().collect(),
+    }
+}
+
+impl ControlFlowGraph {
+    /// The block containing an instruction address.
+    pub fn block_at(&self, address: usize) -> Option<&BasicBlock> {
+        self.blocks.iter().find(|block| block.instructions.contains(&address))
+    }
+
+    /// Exports the graph in Graphviz's DOT language, with each block's instructions as its label.
+    /// Indirect jumps lead to a single `unknown` node, invalid addresses are drawn in red and
+    /// addresses past the end of the program get a dashed node of their own.
+    pub fn to_dot(&self, program: &[i64], opcodes: &OpcodeRegistry) -> String {
+        let mut dot =
+            String::from("digraph cfg {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for block in &self.blocks {
+            let mut label = String::new();
+            for &address in &block.instructions {
+                let (text, _) =
+                    disassemble_instruction(program, address, opcodes).unwrap_or_default();
+                let _ = write!(label, "{}: {}\\l", address, text);
+            }
+            let _ = writeln!(dot, "    b{} [label=\"{}\"];", block.start, label);
+        }
+        for address in &self.invalid {
+            let _ = writeln!(dot, "    b{} [label=\"{}: invalid\", color=red];", address, address);
+        }
+        let outside: BTreeSet<usize> = self
+            .blocks
+            .iter()
+            .flat_map(|block| block.successors.iter().copied())
+            .filter(|&address| address >= program.len())
+            .collect();
+        for address in outside {
+            let _ = writeln!(
+                dot,
+                "    b{} [label=\"{}: outside program\", style=dashed];",
+                address, address
+            );
+        }
+        if self.blocks.iter().any(|block| block.indirect_jump) {
+            let _ = writeln!(dot, "    unknown [label=\"?\", shape=diamond];");
+        }
+        for block in &self.blocks {
+            for successor in &block.successors {
+                let _ = writeln!(dot, "    b{} -> b{};", block.start, successor);
+            }
+            if block.indirect_jump {
+                let _ = writeln!(dot, "    b{} -> unknown [style=dashed];", block.start);
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_program, Event};
+
+    #[test]
+    fn test_control_flow_graph() {
+        // Counts cell 14 down to zero in a loop, then jumps to the address in cell 15
+        let program = parse_program("1001,14,-1,14,1005,14,0,6,14,15,99,6,0,15,3,10").unwrap();
+        let opcodes = OpcodeRegistry::standard();
+        let graph = control_flow_graph(&program, &opcodes);
+        assert_eq!(
+            graph.blocks,
+            vec![
+                BasicBlock {
+                    start: 0,
+                    end: 7,
+                    instructions: vec![0, 4],
+                    successors: vec![7, 0],
+                    indirect_jump: false,
+                },
+                BasicBlock {
+                    start: 7,
+                    end: 10,
+                    instructions: vec![7],
+                    successors: vec![10],
+                    indirect_jump: true,
+                },
+                BasicBlock {
+                    start: 10,
+                    end: 11,
+                    instructions: vec![10],
+                    successors: vec![],
+                    indirect_jump: false,
+                },
+            ]
+        );
+        assert_eq!(graph.block_at(4).map(|block| block.start), Some(0));
+        assert_eq!(
+            graph.to_dot(&program, &opcodes),
+            "digraph cfg {\n    node [shape=box, fontname=\"monospace\"];\n    \
+             b0 [label=\"0: ADD [14], #-1, [14]\\l4: JT [14], #0\\l\"];\n    \
+             b7 [label=\"7: JF [14], [15]\\l\"];\n    \
+             b10 [label=\"10: HLT\\l\"];\n    \
+             unknown [label=\"?\", shape=diamond];\n    \
+             b0 -> b7;\n    b0 -> b0;\n    b7 -> b10;\n    b7 -> unknown [style=dashed];\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_custom_control_flow() {
+        // 99 does nothing, so the program carries on to the custom halt at 2
+        let mut opcodes = OpcodeRegistry::standard();
+        opcodes.define(99, "NOP", &[], ControlFlow::Next, |_| Ok(None));
+        opcodes.define(20, "STOP", &[], ControlFlow::Halt, |_| Ok(Some(Event::Halted)));
+        let program = parse_program("99,99,20,99").unwrap();
+        let graph = control_flow_graph(&program, &opcodes);
+        assert_eq!(graph.blocks.len(), 1);
+        assert_eq!(graph.blocks[0].instructions, vec![0, 1, 2]);
+        assert_eq!(graph.blocks[0].successors, vec![]);
+    }
+
+    #[test]
+    fn test_fallthrough_into_jump_target() {
+        // The jump at 6 goes back to 4, in the middle of the straight line from 0
+        let program = parse_program("1101,1,1,11,4,11,1105,1,4,99,0,0").unwrap();
+        let graph = control_flow_graph(&program, &OpcodeRegistry::standard());
+        let starts: Vec<usize> = graph.blocks.iter().map(|block| block.start).collect();
+        assert_eq!(starts, vec![0, 4]);
+        assert_eq!(graph.blocks[0].successors, vec![4]);
+        assert_eq!(graph.blocks[1].instructions, vec![4, 6]);
+
+        let program = parse_program("1105,1,100").unwrap();
+        let dot = control_flow_graph(&program, &OpcodeRegistry::standard())
+            .to_dot(&program, &OpcodeRegistry::standard());
+        assert!(dot.contains("    b100 [label=\"100: outside program\", style=dashed];\n"));
+        assert!(dot.contains("    b0 -> b100;\n"));
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::io::prelude::*;
+
+use crate::disasm::disassemble_instruction;
+use crate::opcodes::OpcodeRegistry;
+use crate::trace::Tracer;
+
+/// How a memory cell was used while a program ran.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CellCoverage {
+    /// Part of an instruction which ran, either its opcode or a parameter.
+    Executed,
+    /// Only read or written as data.
+    Data,
+    Untouched,
+}
+
+/// A tracer which records which cells ran as instructions, which were only used as data and
+/// which were never touched, to check how much of a program a run exercised.
+#[derive(Debug, Default)]
+pub struct Coverage {
+    /// How many times an instruction ran at each address.
+    executions: HashMap<usize, u64>,
+    /// Every cell of the instructions which ran.
+    code: HashSet<usize>,
+    data: HashSet<usize>,
+}
+
+/// A line of a coverage listing: an instruction, or a single cell which isn't one.
+struct Line {
+    address: usize,
+    len: usize,
+    text: String,
+    is_code: bool,
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    pub fn status(&self, address: usize) -> CellCoverage {
+        if self.code.contains(&address) {
+            CellCoverage::Executed
+        } else if self.data.contains(&address) {
+            CellCoverage::Data
+        } else {
+            CellCoverage::Untouched
+        }
+    }
+
+    /// How many times the instruction at an address ran.
+    pub fn execution_count(&self, address: usize) -> u64 {
+        self.executions.get(&address).copied().unwrap_or(0)
+    }
+
+    /// Splits a program into lines, following the instructions which actually ran so that the
+    /// listing lines up with them, and otherwise only decoding instructions whose cells are all
+    /// untouched.
+    fn lines(&self, program: &[i64], opcodes: &OpcodeRegistry) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let mut address = 0;
+        while address < program.len() {
+            let decoded = disassemble_instruction(program, address, opcodes).filter(|&(_, len)| {
+                self.executions.contains_key(&address)
+                    || (address..address + len)
+                        .all(|cell| self.status(cell) == CellCoverage::Untouched)
+            });
+            let line = match decoded {
+                Some((text, len)) => Line {
+                    address,
+                    len,
+                    text,
+                    is_code: true,
+                },
+                None => Line {
+                    address,
+                    len: 1,
+                    text: format!(".data {}", program[address]),
+                    is_code: self.executions.contains_key(&address),
+                },
+            };
+            address += line.len;
+            lines.push(line);
+        }
+        lines
+    }
+
+    /// Produces a listing of a program with each line marked by how it was covered: the number of
+    /// times an instruction ran, `#####` for an instruction which never ran, `data` for cells only
+    /// used as data, `code` for parameters of instructions listed elsewhere and `-` for anything
+    /// else. Instructions are decoded with the opcodes the program ran with.
+    pub fn listing(&self, program: &[i64], opcodes: &OpcodeRegistry) -> String {
+        let count = |status| (0..program.len()).filter(|&a| self.status(a) == status).count();
+        let mut listing = format!(
+            "{} cells executed, {} data only, {} untouched\n",
+            count(CellCoverage::Executed),
+            count(CellCoverage::Data),
+            count(CellCoverage::Untouched)
+        );
+        for line in self.lines(program, opcodes) {
+            let marker = match (self.executions.get(&line.address), self.status(line.address)) {
+                (Some(count), _) => count.to_string(),
+                (None, CellCoverage::Executed) => "code".to_string(),
+                (None, CellCoverage::Data) => "data".to_string(),
+                (None, CellCoverage::Untouched) if line.is_code => "#####".to_string(),
+                (None, CellCoverage::Untouched) => "-".to_string(),
+            };
+            listing.push_str(&format!("{:>9}: {:5}: {}\n", marker, line.address, line.text));
+        }
+        listing
+    }
+
+    /// Writes the coverage of a program in lcov's tracefile format. Lines are keyed by address,
+    /// with one for each instruction in the listing.
+    pub fn write_lcov(
+        &self,
+        program: &[i64],
+        opcodes: &OpcodeRegistry,
+        name: &str,
+        mut writer: impl Write,
+    ) -> io::Result<()> {
+        writeln!(writer, "TN:")?;
+        writeln!(writer, "SF:{}", name)?;
+        let (mut found, mut hit) = (0, 0);
+        for line in self.lines(program, opcodes).iter().filter(|line| line.is_code) {
+            let count = self.execution_count(line.address);
+            writeln!(writer, "DA:{},{}", line.address, count)?;
+            found += 1;
+            if count > 0 {
+                hit += 1;
+            }
+        }
+        writeln!(writer, "LF:{}", found)?;
+        writeln!(writer, "LH:{}", hit)?;
+        writeln!(writer, "end_of_record")
+    }
+}
+
+impl Tracer for Coverage {
+    fn before_instruction(
+        &mut self,
+        ip: usize,
+        _mnemonic: &'static str,
+        cells: &[i64],
+        _relative_base: i64,
+    ) {
+        *self.executions.entry(ip).or_insert(0) += 1;
+        self.code.extend(ip..ip + cells.len());
+    }
+
+    fn on_write(&mut self, address: usize, _old_value: i64, _new_value: i64) {
+        self.data.insert(address);
+    }
+
+    fn on_read(&mut self, address: usize, _value: i64) {
+        self.data.insert(address);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_program, Machine};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_coverage() {
+        // Reads a value and jumps over the output of 13 to an untouched output of 1 if it's true
+        let program = parse_program("3,12,1005,12,8,4,13,99,104,1,99,0,0,7").unwrap();
+        let coverage = Rc::new(RefCell::new(Coverage::new()));
+        let mut machine = Machine::new(program.clone());
+        machine.set_tracer(Box::new(Rc::clone(&coverage)));
+        machine.push_input(0);
+        assert_eq!(machine.run_until_output(), Ok(Some(7)));
+        assert_eq!(machine.run_until_output(), Ok(None));
+
+        let coverage = coverage.borrow();
+        assert_eq!(coverage.status(6), CellCoverage::Executed);
+        assert_eq!(coverage.status(13), CellCoverage::Data);
+        assert_eq!(coverage.status(8), CellCoverage::Untouched);
+        assert_eq!(
+            coverage.listing(&program, &OpcodeRegistry::standard()).lines().collect::<Vec<_>>(),
+            vec![
+                "8 cells executed, 2 data only, 4 untouched",
+                "        1:     0: IN [12]",
+                "        1:     2: JT [12], #8",
+                "        1:     5: OUT [13]",
+                "        1:     7: HLT",
+                "    #####:     8: OUT #1",
+                "    #####:    10: HLT",
+                "        -:    11: .data 0",
+                "     data:    12: .data 0",
+                "     data:    13: .data 7",
+            ]
+        );
+
+        let mut lcov = Vec::new();
+        coverage.write_lcov(&program, &OpcodeRegistry::standard(), "test.ic", &mut lcov).unwrap();
+        assert_eq!(
+            String::from_utf8(lcov).unwrap(),
+            "TN:\nSF:test.ic\nDA:0,1\nDA:2,1\nDA:5,1\nDA:7,1\nDA:8,0\nDA:10,0\nLF:6\nLH:4\n\
+             end_of_record\n"
+        );
+    }
+}
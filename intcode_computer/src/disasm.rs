@@ -0,0 +1,120 @@
+use crate::machine::{Opcode, ParameterMode};
+use crate::opcodes::OpcodeRegistry;
+
+fn format_operand(mode: ParameterMode, value: i64) -> String {
+    match mode {
+        ParameterMode::Position => format!("[{}]", value),
+        ParameterMode::Immediate => format!("#{}", value),
+        ParameterMode::Relative if value < 0 => format!("rb{}", value),
+        ParameterMode::Relative => format!("rb+{}", value),
+    }
+}
+
+/// Decodes the instruction at the given address into its text and length in cells. Returns None
+/// if the value there isn't the start of a complete instruction that the interpreter would accept.
+pub fn disassemble_instruction(
+    program: &[i64],
+    address: usize,
+    opcodes: &OpcodeRegistry,
+) -> Option<(String, usize)> {
+    let opcode = Opcode::from_value(*program.get(address)?)?;
+    let info = opcodes.info(opcode.code)?;
+    if address + info.param_count() >= program.len() {
+        return None;
+    }
+
+    for index in 0..info.param_count() {
+        if info.writes(index) && opcode.param_mode(index) == ParameterMode::Immediate {
+            return None;
+        }
+    }
+    let len = info.param_count() + 1;
+    Some((format_instruction(info.mnemonic, &program[address..address + len]), len))
+}
+
+/// Formats an instruction already decoded into its mnemonic and cells, the opcode followed by its
+/// parameters.
+pub(crate) fn format_instruction(mnemonic: &str, cells: &[i64]) -> String {
+    let opcode = match Opcode::from_value(cells[0]) {
+        Some(opcode) => opcode,
+        None => return format!(".data {}", cells[0]),
+    };
+    let operands: Vec<String> = cells[1..]
+        .iter()
+        .enumerate()
+        .map(|(index, &value)| format_operand(opcode.param_mode(index), value))
+        .collect();
+    if operands.is_empty() {
+        mnemonic.to_string()
+    } else {
+        format!("{} {}", mnemonic, operands.join(", "))
+    }
+}
+
+/// Produces a listing of a program, one instruction per line, prefixed by its address. Anything
+/// that can't be decoded as an instruction is listed as a `.data` cell holding its raw value, so
+/// the listing assembles back into the same program.
+///
+/// An instruction with extra mode digits beyond its parameters runs, but wouldn't assemble back
+/// to the same value, so it's listed as `.data` cells covering the whole instruction with the
+/// decoded text in a comment.
+pub fn disassemble(program: &[i64], opcodes: &OpcodeRegistry) -> String {
+    let mut listing = String::new();
+    let mut address = 0;
+    while address < program.len() {
+        let (text, len) = match disassemble_instruction(program, address, opcodes) {
+            Some((text, len)) if program[address] >= 10i64.pow(len as u32 + 1) => {
+                let cells: Vec<String> =
+                    program[address..address + len].iter().map(|cell| cell.to_string()).collect();
+                (format!(".data {} ; {}", cells.join(", "), text), len)
+            }
+            Some(instruction) => instruction,
+            None => (format!(".data {}", program[address]), 1),
+        };
+        listing.push_str(&format!("{:5}: {}\n", address, text));
+        address += len;
+    }
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_program;
+
+    #[test]
+    fn test_disassemble() {
+        let program = parse_program("1001,4,-3,4,109,-2,21108,7,8,1,1105,1,0,99,42,0").unwrap();
+        assert_eq!(
+            disassemble(&program, &OpcodeRegistry::standard()).lines().collect::<Vec<_>>(),
+            vec![
+                "    0: ADD [4], #-3, [4]",
+                "    4: ARB #-2",
+                "    6: EQ #7, #8, rb+1",
+                "   10: JT #1, #0",
+                "   13: HLT",
+                "   14: .data 42",
+                "   15: .data 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_instructions_are_data() {
+        // Immediate-mode write, bad parameter mode, truncated instruction
+        let program = parse_program("11101,0,0,0,304,8,1,2").unwrap();
+        assert_eq!(
+            disassemble(&program, &OpcodeRegistry::standard()).lines().collect::<Vec<_>>(),
+            vec![
+                "    0: .data 11101",
+                "    1: .data 0",
+                "    2: .data 0",
+                "    3: .data 0",
+                "    4: .data 304",
+                "    5: .data 8",
+                "    6: .data 1",
+                "    7: .data 2",
+            ]
+        );
+    }
+}
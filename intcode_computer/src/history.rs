@@ -0,0 +1,132 @@
+use std::collections::VecDeque;
+
+/// A write to memory made by the program, as remembered by a machine's history.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteRecord<W = i64> {
+    /// The address of the instruction which made the write.
+    pub ip: usize,
+    pub address: usize,
+    pub old_value: W,
+    pub new_value: W,
+}
+
+/// Everything needed to undo one instruction.
+pub(crate) struct UndoRecord<W> {
+    pub(crate) ip: usize,
+    pub(crate) relative_base: W,
+    pub(crate) writes: Vec<WriteRecord<W>>,
+    pub(crate) inputs: Vec<W>,
+}
+
+/// An undo log of the most recent instructions a machine has executed, so it can be run
+/// backwards.
+pub(crate) struct History<W> {
+    records: VecDeque<UndoRecord<W>>,
+    max_steps: usize,
+    /// Writes and inputs made by the instruction which is running.
+    writes: Vec<WriteRecord<W>>,
+    inputs: Vec<W>,
+}
+
+impl<W> History<W> {
+    pub(crate) fn new(max_steps: usize) -> History<W> {
+        History {
+            records: VecDeque::new(),
+            max_steps,
+            writes: Vec::new(),
+            inputs: Vec::new(),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Forgets anything left over from an instruction which didn't finish.
+    pub(crate) fn begin(&mut self) {
+        self.writes.clear();
+        self.inputs.clear();
+    }
+
+    pub(crate) fn record_write(&mut self, write: WriteRecord<W>) {
+        self.writes.push(write);
+    }
+
+    pub(crate) fn record_input(&mut self, value: W) {
+        self.inputs.push(value);
+    }
+
+    /// Finishes the record for an instruction, given the state from before it ran.
+    pub(crate) fn commit(&mut self, ip: usize, relative_base: W) {
+        if self.max_steps == 0 {
+            return;
+        }
+        if self.records.len() == self.max_steps {
+            self.records.pop_front();
+        }
+        self.records.push_back(UndoRecord {
+            ip,
+            relative_base,
+            writes: self.writes.drain(..).collect(),
+            inputs: self.inputs.drain(..).collect(),
+        });
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<UndoRecord<W>> {
+        self.records.pop_back()
+    }
+
+    /// The most recent write to an address still in the history.
+    pub(crate) fn last_write(&self, address: usize) -> Option<&WriteRecord<W>> {
+        self.records
+            .iter()
+            .rev()
+            .flat_map(|record| record.writes.iter().rev())
+            .find(|write| write.address == address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parse_program, Event, Machine};
+
+    #[test]
+    fn test_step_back() {
+        // Reads a value, doubles it into cell 11 and outputs it, then adds 1 to the base
+        let mut machine = Machine::new(parse_program("3,11,1002,11,2,11,4,11,109,1,99,0").unwrap());
+        machine.record_history(100);
+        machine.push_input(21);
+        assert_eq!(machine.run_until_output(), Ok(Some(42)));
+        assert_eq!(machine.run_until_event(), Ok(Event::Halted));
+        assert_eq!(machine.history_len(), 4);
+        assert_eq!(machine.relative_base(), 1);
+
+        let write = machine.last_write(11).unwrap();
+        assert_eq!((write.ip, write.old_value, write.new_value), (2, 21, 42));
+
+        assert!(machine.step_back());
+        assert_eq!((machine.ip(), machine.relative_base()), (8, 0));
+        assert!(machine.reverse_continue(|machine| machine.ip() == 2));
+        assert_eq!(machine.read(11), 21);
+        assert!(!machine.reverse_continue(|_| false));
+        assert_eq!((machine.ip(), machine.read(11)), (0, 0));
+        assert_eq!(machine.pending_inputs(), &[21]);
+        assert_eq!(machine.inputs_read(), 0);
+        assert!(!machine.step_back());
+
+        // Running forward again gives the same result
+        assert_eq!(machine.run_until_output(), Ok(Some(42)));
+    }
+
+    #[test]
+    fn test_history_limit() {
+        let mut machine = Machine::new(parse_program("1101,1,1,9,1101,2,2,9,99,0").unwrap());
+        machine.record_history(1);
+        assert_eq!(machine.run_until_output(), Ok(None));
+        assert_eq!(machine.history_len(), 1);
+        assert_eq!(machine.last_write(9).map(|write| write.old_value), Some(2));
+        assert!(machine.step_back());
+        assert!(!machine.step_back());
+        assert_eq!((machine.ip(), machine.read(9)), (4, 2));
+    }
+}
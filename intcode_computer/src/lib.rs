@@ -1,378 +1,440 @@
+use std::error::Error;
+use std::fmt;
 use std::io;
 
-#[derive(PartialEq, Copy, Clone, Debug)]
-enum ParameterMode {
-    Position,
-    Immediate,
-    Relative,
-}
+mod amplifiers;
+mod ascii;
+mod asm;
+mod bignum;
+mod cfg;
+mod coverage;
+mod disasm;
+mod history;
+mod machine;
+mod memory;
+mod network;
+mod opcodes;
+mod profile;
+mod profiler;
+mod self_modifying;
+mod snapshot;
+mod trace;
+mod watch;
+mod word;
+
+pub use amplifiers::{AmplifierPipeline, Topology};
+pub use ascii::{ascii_codes, run_ascii, run_machine_ascii_terminal, AsciiOutput};
+pub use asm::{assemble, AsmError};
+pub use bignum::{BigInt, ParseBigIntError};
+pub use cfg::{control_flow_graph, BasicBlock, ControlFlowGraph};
+pub use coverage::{CellCoverage, Coverage};
+pub use disasm::{disassemble, disassemble_instruction};
+pub use history::WriteRecord;
+pub use machine::{Budget, Event, Instruction, Machine};
+pub use network::{BlockedMachine, MachineStatus, Network, NetworkError};
+pub use opcodes::{ControlFlow, HandlerResult, OpcodeRegistry, ParamKind};
+pub use profile::{validate_program, Profile, Validation};
+pub use profiler::Profiler;
+pub use self_modifying::{check_predecode, CodeWrite, PredecodeWarning, SelfModificationDetector};
+pub use snapshot::{Snapshot, SnapshotError};
+pub use trace::{read_binary_trace, BinaryTracer, PrintTracer, TraceRecord, Tracer};
+pub use watch::{Access, WatchAction, WatchHit};
+pub use word::Word;
 
-#[derive(Debug)]
-struct Opcode {
-    code: u32,
-    param_1_mode: ParameterMode,
-    param_2_mode: ParameterMode,
-    param_3_mode: ParameterMode,
+/// Everything that can go wrong while loading or running an Intcode program.
+///
+/// Runtime errors carry the instruction pointer and the raw instruction being executed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IntcodeError<W = i64> {
+    UnknownOpcode {
+        ip: usize,
+        instruction: W,
+    },
+    BadParameterMode {
+        ip: usize,
+        instruction: W,
+    },
+    ImmediateWrite {
+        ip: usize,
+        instruction: W,
+    },
+    NegativeAddress {
+        ip: usize,
+        instruction: W,
+        address: W,
+    },
+    /// An address which is too large to fit in memory.
+    AddressTooLarge {
+        ip: usize,
+        instruction: W,
+        address: W,
+    },
+    /// Arithmetic overflowed the word type in strict mode.
+    Overflow {
+        ip: usize,
+        instruction: W,
+        operand_1: W,
+        operand_2: W,
+    },
+    /// A jump in strict mode to an address outside the program's memory.
+    JumpOutOfRange {
+        ip: usize,
+        instruction: W,
+        target: W,
+    },
+    /// An instruction which isn't part of the profile the machine is restricted to.
+    NotInProfile {
+        ip: usize,
+        instruction: W,
+        profile: Profile,
+    },
+    MalformedInteger {
+        index: usize,
+        text: String,
+    },
+    InputExhausted {
+        ip: usize,
+        instruction: W,
+    },
+    /// The machine ran out of instructions or time before finishing. The snapshot holds its state
+    /// at the point it stopped, so it can be inspected or restored and resumed.
+    BudgetExhausted {
+        ip: usize,
+        instruction: W,
+        snapshot: Box<Snapshot<W>>,
+    },
 }
 
-impl Default for Opcode {
-    fn default() -> Self {
-        Self {
-            code: 0,
-            param_1_mode: ParameterMode::Position,
-            param_2_mode: ParameterMode::Position,
-            param_3_mode: ParameterMode::Position,
+impl<W: fmt::Display> fmt::Display for IntcodeError<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntcodeError::UnknownOpcode {
+                ip,
+                instruction,
+            } => {
+                write!(f, "Unknown opcode in instruction {} at position {}", instruction, ip)
+            }
+            IntcodeError::BadParameterMode {
+                ip,
+                instruction,
+            } => {
+                write!(f, "Bad parameter mode in instruction {} at position {}", instruction, ip)
+            }
+            IntcodeError::ImmediateWrite {
+                ip,
+                instruction,
+            } => write!(
+                f,
+                "Instruction {} at position {} writes to an immediate-mode parameter",
+                instruction, ip
+            ),
+            IntcodeError::NegativeAddress {
+                ip,
+                instruction,
+                address,
+            } => write!(
+                f,
+                "Instruction {} at position {} accesses negative address {}",
+                instruction, ip, address
+            ),
+            IntcodeError::AddressTooLarge {
+                ip,
+                instruction,
+                address,
+            } => write!(
+                f,
+                "Instruction {} at position {} accesses address {}, which is too large",
+                instruction, ip, address
+            ),
+            IntcodeError::Overflow {
+                ip,
+                instruction,
+                operand_1,
+                operand_2,
+            } => write!(
+                f,
+                "Instruction {} at position {} overflows with operands {} and {}",
+                instruction, ip, operand_1, operand_2
+            ),
+            IntcodeError::JumpOutOfRange {
+                ip,
+                instruction,
+                target,
+            } => write!(
+                f,
+                "Instruction {} at position {} jumps to {}, outside the program",
+                instruction, ip, target
+            ),
+            IntcodeError::NotInProfile {
+                ip,
+                instruction,
+                profile,
+            } => write!(
+                f,
+                "Instruction {} at position {} isn't supported by the {} profile",
+                instruction, ip, profile
+            ),
+            IntcodeError::MalformedInteger {
+                index,
+                text,
+            } => {
+                write!(f, "Malformed integer '{}' at index {}", text, index)
+            }
+            IntcodeError::InputExhausted {
+                ip,
+                instruction,
+            } => write!(
+                f,
+                "Instruction {} at position {} requested input but none was available",
+                instruction, ip
+            ),
+            IntcodeError::BudgetExhausted {
+                ip,
+                instruction,
+                ..
+            } => {
+                write!(f, "Ran out of budget before instruction {} at position {}", instruction, ip)
+            }
         }
     }
 }
 
-fn decode_parameter_mode(encoded: char) -> ParameterMode {
-    match encoded.to_digit(10).unwrap() {
-        0 => ParameterMode::Position,
-        1 => ParameterMode::Immediate,
-        2 => ParameterMode::Relative,
-        _ => panic!("Bad parameter mode {}!", encoded),
-    }
-}
+impl<W: fmt::Debug + fmt::Display> Error for IntcodeError<W> {}
 
-impl Opcode {
-    fn from_string(string: &str) -> Opcode {
-        let code_val = if string.len() == 1 {
-            string.parse().unwrap()
-        } else {
-            string[string.len() - 2..].parse().unwrap()
-        };
-        let mut result = Opcode {
-            code: code_val,
-            ..Default::default()
-        };
-        if string.len() > 2 {
-            let mut iter = string.chars().rev().skip(2);
-            match iter.next() {
-                Some(mode) => result.param_1_mode = decode_parameter_mode(mode),
-                None => return result,
-            }
-            match iter.next() {
-                Some(mode) => result.param_2_mode = decode_parameter_mode(mode),
-                None => return result,
-            }
-            match iter.next() {
-                Some(mode) => result.param_3_mode = decode_parameter_mode(mode),
-                None => return result,
-            }
+fn default_input() -> Option<i64> {
+    loop {
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input).ok()? == 0 {
+            return None;
+        }
+        match input.trim().parse() {
+            Ok(value) => return Some(value),
+            Err(_) => println!("'{}' is not a valid integer, try again.", input.trim()),
         }
-        result
     }
 }
 
-#[derive(Default)]
-struct Program {
-    text: Vec<String>,
-    current_pos: usize,
-    relative_base: isize,
+fn default_output(output: i64) {
+    println!("{}", output);
 }
 
-impl Program {
-    fn print_diagnostic(&self, prefix: &str) {
-        println!(
-            "{}: Position: {} Code at position: {} Relative Base: {}",
-            prefix, self.current_pos, self.text[self.current_pos], self.relative_base
-        );
-    }
+/// The initial memory of an Intcode program.
+pub type Program = Vec<i64>;
+
+/// A token in a program's source that isn't a valid integer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// Zero-based index of the token, i.e. the address it would have been loaded at.
+    pub index: usize,
+    pub text: String,
 }
 
-fn get_param_value(mode: ParameterMode, param: isize, program: &Program) -> isize {
-    match mode {
-        ParameterMode::Immediate => param,
-        ParameterMode::Position => {
-            assert!((param as usize) < program.text.len(), "Index {} out of program range!", param);
-            program.text[param as usize].trim().parse().unwrap()
-        }
-        ParameterMode::Relative => {
-            let pos = (param + program.relative_base) as usize;
-            assert!(pos < program.text.len(), "Index {} out of program range!", param);
-            program.text[pos].trim().parse().unwrap()
-        }
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Token {} ('{}') is not a valid integer", self.index, self.text)
     }
 }
 
-fn get_write_location(mode: ParameterMode, param: isize, program: &Program) -> usize {
-    match mode {
-        ParameterMode::Immediate => panic!("Write location cannot be immediate!"),
-        ParameterMode::Position => {
-            assert!((param as usize) < program.text.len(), "Index {} out of write range!", param);
-            param as usize
-        }
-        ParameterMode::Relative => {
-            let pos = (param + program.relative_base) as usize;
-            assert!(pos < program.text.len(), "Index {} out of write range!", param);
-            pos
+impl Error for ParseError {}
+
+impl<W> From<ParseError> for IntcodeError<W> {
+    fn from(error: ParseError) -> Self {
+        IntcodeError::MalformedInteger {
+            index: error.index,
+            text: error.text,
         }
     }
 }
 
-fn handle_add(
-    opcode: &Opcode,
-    a_pos: isize,
-    b_pos: isize,
-    result_pos: isize,
-    program: &mut Program,
-) {
-    let operand_1 = get_param_value(opcode.param_1_mode, a_pos, program);
-    let operand_2 = get_param_value(opcode.param_2_mode, b_pos, program);
-    let result_pos = get_write_location(opcode.param_3_mode, result_pos, program);
-    program.text[result_pos] = (operand_1 + operand_2).to_string();
-    program.current_pos += 4;
+/// Parses the comma-separated source of an Intcode program. Whitespace and newlines around
+/// tokens are ignored, as is a trailing comma.
+pub fn parse_program(source: &str) -> Result<Program, ParseError> {
+    parse_words(source)
 }
 
-fn handle_mult(
-    opcode: &Opcode,
-    a_pos: isize,
-    b_pos: isize,
-    result_pos: isize,
-    program: &mut Program,
-) {
-    let operand_1 = get_param_value(opcode.param_1_mode, a_pos, program);
-    let operand_2 = get_param_value(opcode.param_2_mode, b_pos, program);
-    let result_pos = get_write_location(opcode.param_3_mode, result_pos, program);
-    program.text[result_pos] = (operand_1 * operand_2).to_string();
-    program.current_pos += 4;
+/// Like parse_program, but for running on a machine with a different word type.
+pub fn parse_words<W: Word>(source: &str) -> Result<Vec<W>, ParseError> {
+    let mut tokens: Vec<&str> = source.split(',').map(|token| token.trim()).collect();
+    if tokens.last() == Some(&"") {
+        tokens.pop();
+    }
+    tokens
+        .into_iter()
+        .enumerate()
+        .map(|(index, token)| {
+            token.parse().map_err(|_| ParseError {
+                index,
+                text: token.to_string(),
+            })
+        })
+        .collect()
 }
 
-fn handle_input(
-    opcode: &Opcode,
-    pos: isize,
-    program: &mut Program,
-    input_fn: &mut impl FnMut() -> String,
-) {
-    let pos = get_write_location(opcode.param_1_mode, pos, program);
-    // parse() and to_string() to make sure we are getting a valid integer
-    program.text[pos] = input_fn().parse::<isize>().unwrap().to_string();
-    program.current_pos += 2;
+pub fn run(input: Program) -> Result<(), IntcodeError> {
+    run_with_custom_io(input, &mut default_input, &mut default_output)
 }
 
-fn handle_output(
-    opcode: &Opcode,
-    param: isize,
-    program: &mut Program,
-    output_fn: &mut impl FnMut(&str),
-) {
-    let param = get_param_value(opcode.param_1_mode, param, program);
-    output_fn(&param.to_string());
-    program.current_pos += 2;
+/// Runs a program until it halts, calling input_fn whenever it needs input (returning None
+/// fails the run with IntcodeError::InputExhausted) and output_fn for every value it outputs.
+pub fn run_with_custom_io(
+    input: Program,
+    input_fn: &mut impl FnMut() -> Option<i64>,
+    output_fn: &mut impl FnMut(i64),
+) -> Result<(), IntcodeError> {
+    run_machine_with_custom_io(&mut Machine::new(input), input_fn, output_fn)
 }
 
-fn handle_jump_if_true(opcode: &Opcode, param: isize, pos: isize, program: &mut Program) {
-    let param = get_param_value(opcode.param_1_mode, param, program);
-    let pos = get_param_value(opcode.param_2_mode, pos, program);
-    if param != 0 {
-        program.current_pos = pos as usize;
-    } else {
-        program.current_pos += 3;
-    }
+/// Like run, but for a machine that's already been set up, e.g. with a tracer attached.
+pub fn run_machine(machine: &mut Machine) -> Result<(), IntcodeError> {
+    run_machine_with_custom_io(machine, &mut default_input, &mut default_output)
 }
 
-fn handle_jump_if_false(opcode: &Opcode, param: isize, pos: isize, program: &mut Program) {
-    let param = get_param_value(opcode.param_1_mode, param, program);
-    let pos = get_param_value(opcode.param_2_mode, pos, program);
-    if param == 0 {
-        program.current_pos = pos as usize;
-    } else {
-        program.current_pos += 3;
+/// Like run_with_custom_io, but for a machine that's already been set up.
+pub fn run_machine_with_custom_io<W: Word>(
+    machine: &mut Machine<W>,
+    input_fn: &mut impl FnMut() -> Option<W>,
+    output_fn: &mut impl FnMut(W),
+) -> Result<(), IntcodeError<W>> {
+    loop {
+        match machine.run_until_event()? {
+            Event::NeedsInput => match input_fn() {
+                Some(value) => machine.push_input(value),
+                None => return Err(machine.input_exhausted()),
+            },
+            Event::Output(value) => output_fn(value),
+            Event::Halted => return Ok(()),
+            Event::Watchpoint(_) => (),
+        }
     }
 }
 
-fn handle_less_than(
-    opcode: &Opcode,
-    param_1: isize,
-    param_2: isize,
-    result_pos: isize,
-    program: &mut Program,
-) {
-    let param_1 = get_param_value(opcode.param_1_mode, param_1, program);
-    let param_2 = get_param_value(opcode.param_2_mode, param_2, program);
-    let result_pos = get_write_location(opcode.param_3_mode, result_pos, program);
-    program.text[result_pos] = if param_1 < param_2 {
-        "1".to_string()
-    } else {
-        "0".to_string()
-    };
-    program.current_pos += 4
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-fn handle_equals(
-    opcode: &Opcode,
-    param_1: isize,
-    param_2: isize,
-    result_pos: isize,
-    program: &mut Program,
-) {
-    let param_1 = get_param_value(opcode.param_1_mode, param_1, program);
-    let param_2 = get_param_value(opcode.param_2_mode, param_2, program);
-    let result_pos = get_write_location(opcode.param_3_mode, result_pos, program);
-    program.text[result_pos] = if param_1 == param_2 {
-        "1".to_string()
-    } else {
-        "0".to_string()
-    };
-    program.current_pos += 4
-}
+    #[test]
+    fn test_parse_program() {
+        assert_eq!(parse_program("1,0, -3,99\n"), Ok(vec![1, 0, -3, 99]));
+        assert_eq!(parse_program(" 1,\n2,\r\n3,\n"), Ok(vec![1, 2, 3]));
+        assert_eq!(parse_program(""), Ok(vec![]));
+    }
 
-fn handle_adjust_relative_base(opcode: &Opcode, param: isize, program: &mut Program) {
-    let resolved_param = get_param_value(opcode.param_1_mode, param, program);
-    program.relative_base += resolved_param;
-    program.current_pos += 2;
-}
+    #[test]
+    fn test_parse_program_reports_bad_tokens() {
+        assert_eq!(
+            parse_program("1,0,x,99"),
+            Err(ParseError {
+                index: 2,
+                text: "x".to_string()
+            })
+        );
+        assert_eq!(
+            parse_program("1,,99"),
+            Err(ParseError {
+                index: 1,
+                text: "".to_string()
+            })
+        );
+        assert_eq!(
+            parse_program("1,2,3,4 5"),
+            Err(ParseError {
+                index: 3,
+                text: "4 5".to_string()
+            })
+        );
+    }
 
-fn default_input() -> String {
-    let mut input = String::new();
-    io::stdin().read_line(&mut input).unwrap();
-    input.trim().to_string()
-}
+    #[test]
+    fn test_run_large_numbers() {
+        let program = parse_program("1102,34915192,34915192,7,4,7,99,0").unwrap();
+        let mut outputs = Vec::new();
+        run_with_custom_io(program, &mut || None, &mut |output| outputs.push(output)).unwrap();
+        assert_eq!(outputs, vec![1219070632396864]);
+    }
 
-fn default_output(output: &str) {
-    println!("{}", output);
-}
+    fn run_words<W: Word>(source: &str) -> Vec<W> {
+        let mut machine = Machine::from_words(parse_words(source).unwrap());
+        let mut outputs = Vec::new();
+        run_machine_with_custom_io(&mut machine, &mut || None, &mut |output| outputs.push(output))
+            .unwrap();
+        outputs
+    }
 
-pub fn run(input: Vec<String>) {
-    run_with_custom_io(input, &mut default_input, &mut default_output);
-}
+    #[test]
+    fn test_run_wide_words() {
+        // 2^62 * 4 overflows an i64
+        let program = "1102,4611686018427387904,4,7,4,7,99,0";
+        assert_eq!(run_words::<i128>(program), vec![1 << 64]);
+        assert_eq!(run_words::<BigInt>(program), vec!["18446744073709551616".parse().unwrap()]);
 
-pub fn run_with_custom_io(
-    input: Vec<String>,
-    input_fn: &mut impl FnMut() -> String,
-    output_fn: &mut impl FnMut(&str),
-) {
-    let mut program = Program {
-        text: input,
-        ..Default::default()
-    };
+        // 2^100 squared is too big even for an i128
+        let program =
+            "1102,1267650600228229401496703205376,1267650600228229401496703205376,7,4,7,99,0";
+        assert_eq!(
+            run_words::<BigInt>(program)[0].to_string(),
+            "1606938044258990275541962092341162602522202993782792835301376"
+        );
+    }
 
-    let mut empty_memory = vec!["0".to_string(); program.text.len() * 3];
-    program.text.append(&mut empty_memory);
+    fn run_expecting_error(program: Vec<i64>) -> IntcodeError {
+        run_with_custom_io(program, &mut || None, &mut |_| ()).unwrap_err()
+    }
 
-    let mut iteration_num: u32 = 0;
-    loop {
-        // program.print_diagnostic(&iteration_num.to_string());
-        let opcode = Opcode::from_string(&program.text[program.current_pos].trim());
-        match opcode.code {
-            1 => {
-                let params: Vec<String> =
-                    program.text[program.current_pos + 1..program.current_pos + 4].to_vec();
-                handle_add(
-                    &opcode,
-                    params[0].parse().unwrap(),
-                    params[1].parse().unwrap(),
-                    params[2].parse().unwrap(),
-                    &mut program,
-                );
-            }
-            2 => {
-                let params: Vec<String> =
-                    program.text[program.current_pos + 1..program.current_pos + 4].to_vec();
-                handle_mult(
-                    &opcode,
-                    params[0].parse().unwrap(),
-                    params[1].parse().unwrap(),
-                    params[2].parse().unwrap(),
-                    &mut program,
-                );
+    #[test]
+    fn test_bad_programs_return_errors() {
+        assert_eq!(
+            run_expecting_error(vec![1101, 1, 1, 0, 42]),
+            IntcodeError::UnknownOpcode {
+                ip: 4,
+                instruction: 42
             }
-            3 => {
-                handle_input(
-                    &opcode,
-                    program.text[program.current_pos + 1].parse().unwrap(),
-                    &mut program,
-                    input_fn,
-                );
+        );
+        assert_eq!(
+            run_expecting_error(vec![301, 0, 0, 0, 99]),
+            IntcodeError::BadParameterMode {
+                ip: 0,
+                instruction: 301
             }
-            4 => {
-                handle_output(
-                    &opcode,
-                    program.text[program.current_pos + 1].parse().unwrap(),
-                    &mut program,
-                    output_fn,
-                );
+        );
+        assert_eq!(
+            run_expecting_error(vec![11101, 1, 1, 0, 99]),
+            IntcodeError::ImmediateWrite {
+                ip: 0,
+                instruction: 11101
             }
-            5 => {
-                handle_jump_if_true(
-                    &opcode,
-                    program.text[program.current_pos + 1].parse().unwrap(),
-                    program.text[program.current_pos + 2].parse().unwrap(),
-                    &mut program,
-                );
+        );
+        assert_eq!(
+            run_expecting_error(vec![4, -1, 99]),
+            IntcodeError::NegativeAddress {
+                ip: 0,
+                instruction: 4,
+                address: -1
             }
-            6 => {
-                handle_jump_if_false(
-                    &opcode,
-                    program.text[program.current_pos + 1].parse().unwrap(),
-                    program.text[program.current_pos + 2].parse().unwrap(),
-                    &mut program,
-                );
+        );
+        assert_eq!(
+            run_expecting_error(vec![3, 0, 99]),
+            IntcodeError::InputExhausted {
+                ip: 0,
+                instruction: 3
             }
-            7 => handle_less_than(
-                &opcode,
-                program.text[program.current_pos + 1].parse().unwrap(),
-                program.text[program.current_pos + 2].parse().unwrap(),
-                program.text[program.current_pos + 3].parse().unwrap(),
-                &mut program,
-            ),
-            8 => handle_equals(
-                &opcode,
-                program.text[program.current_pos + 1].parse().unwrap(),
-                program.text[program.current_pos + 2].parse().unwrap(),
-                program.text[program.current_pos + 3].parse().unwrap(),
-                &mut program,
-            ),
-            9 => handle_adjust_relative_base(
-                &opcode,
-                program.text[program.current_pos + 1].parse().unwrap(),
-                &mut program,
-            ),
-            99 => return,
-            _ => panic!(
-                "Bad opcode {} Pos: {} Str: {} Iteration: {}!",
-                opcode.code, program.current_pos, program.text[program.current_pos], iteration_num
-            ),
-        };
-        iteration_num += 1;
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_full_code_parsed_successfully() {
-        let opcode = Opcode::from_string("11101");
-        assert_eq!(opcode.code, 1);
-        assert_eq!(opcode.param_1_mode, ParameterMode::Immediate);
-        assert_eq!(opcode.param_2_mode, ParameterMode::Immediate);
-        assert_eq!(opcode.param_3_mode, ParameterMode::Immediate);
-
-        let opcode = Opcode::from_string("21002");
-        assert_eq!(opcode.code, 2);
-        assert_eq!(opcode.param_1_mode, ParameterMode::Position);
-        assert_eq!(opcode.param_2_mode, ParameterMode::Immediate);
-        assert_eq!(opcode.param_3_mode, ParameterMode::Relative);
+        );
     }
 
     #[test]
-    fn test_leading_zero_suppression() {
-        let opcode = Opcode::from_string("03");
-        assert_eq!(opcode.code, 3);
-        assert_eq!(opcode.param_1_mode, ParameterMode::Position);
-        assert_eq!(opcode.param_2_mode, ParameterMode::Position);
-        assert_eq!(opcode.param_3_mode, ParameterMode::Position);
+    fn test_run_quine() {
+        let program =
+            parse_program("109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99").unwrap();
+        let mut outputs = Vec::new();
+        run_with_custom_io(program.clone(), &mut || None, &mut |output| outputs.push(output))
+            .unwrap();
+        assert_eq!(outputs, program);
     }
 
     #[test]
-    fn test_single_digit() {
-        let opcode = Opcode::from_string("3");
-        assert_eq!(opcode.code, 3);
-        assert_eq!(opcode.param_1_mode, ParameterMode::Position);
-        assert_eq!(opcode.param_2_mode, ParameterMode::Position);
-        assert_eq!(opcode.param_3_mode, ParameterMode::Position);
+    fn test_far_writes_grow_memory() {
+        let program = parse_program("1101,3,4,1000000,4,1000000,99").unwrap();
+        let mut outputs = Vec::new();
+        run_with_custom_io(program, &mut || None, &mut |output| outputs.push(output)).unwrap();
+        assert_eq!(outputs, vec![7]);
     }
 }
@@ -0,0 +1,1035 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::history::{History, WriteRecord};
+use crate::memory::Memory;
+use crate::opcodes::{HandlerResult, OpcodeRegistry, ParamKind};
+use crate::profile::Profile;
+use crate::snapshot::Snapshot;
+use crate::trace::Tracer;
+use crate::watch::{Access, MemoryAccess, WatchAction, WatchHit, Watchpoint};
+use crate::word::Word;
+use crate::IntcodeError;
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Debug)]
+pub(crate) enum ParameterMode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+#[derive(Debug)]
+pub(crate) struct Opcode {
+    pub(crate) code: u32,
+    pub(crate) param_1_mode: ParameterMode,
+    pub(crate) param_2_mode: ParameterMode,
+    pub(crate) param_3_mode: ParameterMode,
+}
+
+fn decode_parameter_mode(encoded: i64) -> Option<ParameterMode> {
+    match encoded {
+        0 => Some(ParameterMode::Position),
+        1 => Some(ParameterMode::Immediate),
+        2 => Some(ParameterMode::Relative),
+        _ => None,
+    }
+}
+
+impl Opcode {
+    /// Returns None if any of the parameter modes are invalid.
+    pub(crate) fn from_value(value: i64) -> Option<Opcode> {
+        Some(Opcode {
+            code: (value % 100) as u32,
+            param_1_mode: decode_parameter_mode(value / 100 % 10)?,
+            param_2_mode: decode_parameter_mode(value / 1000 % 10)?,
+            param_3_mode: decode_parameter_mode(value / 10000 % 10)?,
+        })
+    }
+
+    /// Gets the mode of the parameter at the given zero-based index.
+    pub(crate) fn param_mode(&self, index: usize) -> ParameterMode {
+        match index {
+            0 => self.param_1_mode,
+            1 => self.param_2_mode,
+            _ => self.param_3_mode,
+        }
+    }
+
+    #[cfg(test)]
+    fn from_string(string: &str) -> Opcode {
+        Opcode::from_value(string.trim().parse().unwrap()).unwrap()
+    }
+}
+
+/// How often to look at the clock when running with a deadline, in instructions.
+const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+/// Limits on how much longer a machine may run. When either is used up, the machine stops with
+/// IntcodeError::BudgetExhausted; give it a new budget to carry on.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Budget {
+    pub max_instructions: Option<u64>,
+    pub deadline: Option<Instant>,
+}
+
+impl Budget {
+    pub fn instructions(max_instructions: u64) -> Budget {
+        Budget {
+            max_instructions: Some(max_instructions),
+            deadline: None,
+        }
+    }
+
+    pub fn timeout(timeout: Duration) -> Budget {
+        Budget {
+            max_instructions: None,
+            deadline: Some(Instant::now() + timeout),
+        }
+    }
+}
+
+/// The reasons a machine can stop running and hand control back to its caller.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event<W = i64> {
+    /// The machine is at an input instruction and its input queue is empty. Push some input and
+    /// run it again to continue.
+    NeedsInput,
+    Output(W),
+    Halted,
+    /// A watchpoint set to pause was triggered by the instruction that just ran.
+    Watchpoint(WatchHit<W>),
+}
+
+/// An Intcode machine which can be run a bit at a time, pausing whenever it produces output or
+/// needs input that hasn't been given to it yet.
+///
+/// Memory cells hold i64 values unless another Word type is chosen, e.g. `Machine<BigInt>`.
+pub struct Machine<W = i64> {
+    memory: Memory<W>,
+    ip: usize,
+    relative_base: W,
+    inputs: VecDeque<W>,
+    tracer: Option<Box<dyn Tracer<W>>>,
+    budget: Budget,
+    instructions_executed: u64,
+    inputs_read: u64,
+    strict: bool,
+    opcodes: Rc<OpcodeRegistry<W>>,
+    profile: Option<Profile>,
+    watchpoints: Vec<Watchpoint<W>>,
+    next_watchpoint_id: usize,
+    /// Accesses made by the current instruction, only recorded while there are watchpoints or a
+    /// tracer to tell about them.
+    accesses: RefCell<Vec<MemoryAccess<W>>>,
+    /// Hits waiting to be returned as events, when an instruction triggers more than one or also
+    /// produces an event of its own.
+    watch_hits: VecDeque<WatchHit<W>>,
+    history: Option<History<W>>,
+    /// Whether the tracer has already been told about the halt the machine is sitting on, so
+    /// stepping a halted machine again doesn't trace it over and over.
+    halt_traced: bool,
+}
+
+impl Machine {
+    /// Creates a machine with i64 cells. Use `Machine::from_words` for other word types.
+    pub fn new(program: Vec<i64>) -> Machine {
+        Machine::from_words(program)
+    }
+}
+
+impl<W: Word> Machine<W> {
+    /// Creates a machine running a program of any word type.
+    pub fn from_words(program: Vec<W>) -> Machine<W> {
+        Machine {
+            memory: Memory::new(program),
+            ip: 0,
+            relative_base: W::from_i64(0),
+            inputs: VecDeque::new(),
+            tracer: None,
+            budget: Budget::default(),
+            instructions_executed: 0,
+            inputs_read: 0,
+            strict: false,
+            opcodes: Rc::new(OpcodeRegistry::standard()),
+            profile: None,
+            watchpoints: Vec::new(),
+            next_watchpoint_id: 0,
+            accesses: RefCell::new(Vec::new()),
+            watch_hits: VecDeque::new(),
+            history: None,
+            halt_traced: false,
+        }
+    }
+
+    /// Attaches a tracer which is told about everything the machine does from now on, replacing
+    /// any tracer that was already attached.
+    pub fn set_tracer(&mut self, tracer: Box<dyn Tracer<W>>) {
+        self.tracer = Some(tracer);
+    }
+
+    pub fn take_tracer(&mut self) -> Option<Box<dyn Tracer<W>>> {
+        self.tracer.take()
+    }
+
+    /// Captures the machine's full state so it can be restored later.
+    pub fn snapshot(&self) -> Snapshot<W> {
+        Snapshot {
+            memory: self.memory.clone(),
+            ip: self.ip,
+            relative_base: self.relative_base.clone(),
+            inputs: self.inputs.clone(),
+        }
+    }
+
+    /// Creates a machine in the state captured by a snapshot, ready to carry on running.
+    pub fn restore(snapshot: Snapshot<W>) -> Machine<W> {
+        Machine {
+            memory: snapshot.memory,
+            ip: snapshot.ip,
+            relative_base: snapshot.relative_base,
+            inputs: snapshot.inputs,
+            tracer: None,
+            budget: Budget::default(),
+            instructions_executed: 0,
+            inputs_read: 0,
+            strict: false,
+            opcodes: Rc::new(OpcodeRegistry::standard()),
+            profile: None,
+            watchpoints: Vec::new(),
+            next_watchpoint_id: 0,
+            accesses: RefCell::new(Vec::new()),
+            watch_hits: VecDeque::new(),
+            history: None,
+            halt_traced: false,
+        }
+    }
+
+    /// Limits how much longer the machine may run, replacing any previous budget.
+    pub fn set_budget(&mut self, budget: Budget) {
+        self.budget = budget;
+    }
+
+    pub fn budget(&self) -> Budget {
+        self.budget
+    }
+
+    /// The number of instructions this machine has executed, not counting halts.
+    pub fn instructions_executed(&self) -> u64 {
+        self.instructions_executed
+    }
+
+    /// In strict mode, arithmetic which overflows the word type and jumps to addresses outside the
+    /// program's memory are errors, rather than wrapping around or carrying on into zeroes.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Replaces the opcodes the machine understands, e.g. to add custom instructions.
+    pub fn set_opcodes(&mut self, opcodes: OpcodeRegistry<W>) {
+        self.opcodes = Rc::new(opcodes);
+    }
+
+    pub fn opcodes(&self) -> &OpcodeRegistry<W> {
+        &self.opcodes
+    }
+
+    /// Restricts the machine to the instructions and parameter modes in a profile, so running a
+    /// program which needs more fails with IntcodeError::NotInProfile.
+    pub fn set_profile(&mut self, profile: Profile) {
+        self.profile = Some(profile);
+    }
+
+    /// Watches a range of addresses for an access by the program, returning an id which can be
+    /// used to remove the watchpoint again.
+    pub fn add_watchpoint(
+        &mut self,
+        addresses: RangeInclusive<usize>,
+        access: Access,
+        action: WatchAction<W>,
+    ) -> usize {
+        let id = self.next_watchpoint_id;
+        self.next_watchpoint_id += 1;
+        self.watchpoints.push(Watchpoint {
+            id,
+            addresses,
+            access,
+            action,
+        });
+        id
+    }
+
+    /// Removes a watchpoint, returning whether it existed.
+    pub fn remove_watchpoint(&mut self, id: usize) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|watchpoint| watchpoint.id != id);
+        self.watchpoints.len() != count
+    }
+
+    /// Starts keeping an undo log of the last max_steps instructions, so the machine can be run
+    /// backwards. Any history already recorded is discarded.
+    pub fn record_history(&mut self, max_steps: usize) {
+        self.history = Some(History::new(max_steps));
+    }
+
+    /// The number of instructions which can currently be undone.
+    pub fn history_len(&self) -> usize {
+        self.history.as_ref().map_or(0, History::len)
+    }
+
+    /// Undoes the last instruction executed, restoring memory, the instruction pointer, the
+    /// relative base and any input it read. Returns false if there's no history left. Output
+    /// can't be taken back, so running forward again repeats it.
+    pub fn step_back(&mut self) -> bool {
+        let record = match self.history.as_mut().and_then(History::pop) {
+            Some(record) => record,
+            None => return false,
+        };
+        for write in record.writes.into_iter().rev() {
+            self.memory.write(write.address, write.old_value);
+        }
+        for value in record.inputs.into_iter().rev() {
+            self.inputs.push_front(value);
+            self.inputs_read -= 1;
+        }
+        self.ip = record.ip;
+        self.relative_base = record.relative_base;
+        self.instructions_executed -= 1;
+        self.watch_hits.clear();
+        self.halt_traced = false;
+        true
+    }
+
+    /// Steps backwards until `stop` returns true, e.g. when the machine is back at a breakpoint.
+    /// It's always undone at least one instruction. Returns false if the history ran out first.
+    pub fn reverse_continue(&mut self, mut stop: impl FnMut(&Machine<W>) -> bool) -> bool {
+        while self.step_back() {
+            if stop(self) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// The most recent write to an address which is still in the history.
+    pub fn last_write(&self, address: usize) -> Option<&WriteRecord<W>> {
+        self.history.as_ref()?.last_write(address)
+    }
+
+    /// The number of input values this machine has read, so the next one it asks for is input
+    /// number `inputs_read() + 1`.
+    pub fn inputs_read(&self) -> u64 {
+        self.inputs_read
+    }
+
+    pub fn push_input(&mut self, value: W) {
+        self.inputs.push_back(value);
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn relative_base(&self) -> W {
+        self.relative_base.clone()
+    }
+
+    /// Inputs which have been pushed but not yet consumed.
+    pub fn pending_inputs(&self) -> &VecDeque<W> {
+        &self.inputs
+    }
+
+    pub fn read(&self, address: usize) -> W {
+        self.memory.read(address)
+    }
+
+    pub fn write(&mut self, address: usize, value: W) {
+        self.memory.write(address, value);
+        self.halt_traced = false;
+    }
+
+    /// Runs until the program halts, produces output or needs more input.
+    pub fn run_until_event(&mut self) -> Result<Event<W>, IntcodeError<W>> {
+        loop {
+            if let Some(event) = self.step()? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Runs until the program produces output, returning None if it halts first. Running out of
+    /// input is treated as an error, and watchpoints don't stop it.
+    pub fn run_until_output(&mut self) -> Result<Option<W>, IntcodeError<W>> {
+        loop {
+            match self.run_until_event()? {
+                Event::Output(value) => return Ok(Some(value)),
+                Event::Halted => return Ok(None),
+                Event::NeedsInput => return Err(self.input_exhausted()),
+                Event::Watchpoint(_) => (),
+            }
+        }
+    }
+
+    /// Executes a single instruction. Returns the event it caused, if any; a machine that is
+    /// halted or waiting for input doesn't move.
+    pub fn step(&mut self) -> Result<Option<Event<W>>, IntcodeError<W>> {
+        if let Some(hit) = self.watch_hits.pop_front() {
+            return Ok(Some(Event::Watchpoint(hit)));
+        }
+        let instruction = self.param(0)?;
+        let opcode = match instruction.to_i64() {
+            Some(value) => {
+                Opcode::from_value(value).ok_or_else(|| IntcodeError::BadParameterMode {
+                    ip: self.ip,
+                    instruction: instruction.clone(),
+                })?
+            }
+            None => {
+                return Err(IntcodeError::UnknownOpcode {
+                    ip: self.ip,
+                    instruction,
+                })
+            }
+        };
+        let opcodes = Rc::clone(&self.opcodes);
+        let definition = opcodes.get(opcode.code).ok_or_else(|| IntcodeError::UnknownOpcode {
+            ip: self.ip,
+            instruction: instruction.clone(),
+        })?;
+        if let Some(profile) = self.profile {
+            if !profile.allows(&opcode, &self.opcodes) {
+                return Err(IntcodeError::NotInProfile {
+                    ip: self.ip,
+                    instruction: instruction.clone(),
+                    profile,
+                });
+            }
+        }
+        // Checked before running it, so an instruction at the very end of memory has no effect
+        let next_ip = self.offset_from_ip(1 + definition.params.len())?;
+        for (index, kind) in definition.params.iter().enumerate() {
+            if *kind == ParamKind::Write && opcode.param_mode(index) == ParameterMode::Immediate {
+                return Err(IntcodeError::ImmediateWrite {
+                    ip: self.ip,
+                    instruction,
+                });
+            }
+        }
+
+        let ip = self.ip;
+        let relative_base = self.relative_base.clone();
+        let out_of_budget = self.check_budget().err();
+        self.accesses.get_mut().clear();
+        if let Some(history) = self.history.as_mut() {
+            history.begin();
+        }
+        let mut current = Instruction {
+            machine: self,
+            opcode,
+            mnemonic: definition.mnemonic,
+            len: 1 + definition.params.len(),
+            jumped: false,
+            started: false,
+            out_of_budget,
+        };
+        let event = (definition.handler)(&mut current)?;
+        // Halting or waiting for input leaves the machine where it is, so neither uses up any of
+        // the budget, but input that was only held back because the budget ran out is an error
+        let executed = match &event {
+            Some(Event::NeedsInput) => {
+                if !current.machine.inputs.is_empty() {
+                    if let Some(error) = current.out_of_budget.take() {
+                        return Err(error);
+                    }
+                }
+                false
+            }
+            Some(Event::Halted) => {
+                if !current.machine.halt_traced {
+                    current.trace();
+                    current.machine.halt_traced = true;
+                }
+                false
+            }
+            _ => {
+                current.start()?;
+                true
+            }
+        };
+        let jumped = current.jumped;
+        let started = current.started;
+        if executed && !jumped {
+            self.ip = next_ip;
+        }
+        match self.tracer.as_mut() {
+            Some(tracer) if started => {
+                if let Some(Event::Output(value)) = &event {
+                    tracer.on_output(value.clone());
+                }
+                for access in self.accesses.get_mut().iter() {
+                    if access.old_value.is_none() {
+                        tracer.on_read(access.address, access.new_value.clone());
+                    }
+                }
+                tracer.after_instruction(self.ip, self.relative_base.clone(), jumped);
+            }
+            _ => (),
+        }
+        if executed {
+            self.instructions_executed += 1;
+            if let Some(remaining) = self.budget.max_instructions.as_mut() {
+                *remaining -= 1;
+            }
+            if let Some(history) = self.history.as_mut() {
+                history.commit(ip, relative_base);
+            }
+        }
+        self.check_watchpoints(ip);
+        match event {
+            None => Ok(self.watch_hits.pop_front().map(Event::Watchpoint)),
+            event => Ok(event),
+        }
+    }
+
+    /// Runs callbacks for the watchpoints triggered by the instruction at ip, and queues up hits
+    /// for those which pause.
+    fn check_watchpoints(&mut self, ip: usize) {
+        for access in self.accesses.get_mut().drain(..) {
+            for watchpoint in self.watchpoints.iter_mut() {
+                if let Some(hit) = watchpoint.check(ip, &access) {
+                    self.watch_hits.push_back(hit);
+                }
+            }
+        }
+    }
+
+    /// Tells the tracer the instruction at ip, which takes up len cells, is about to run.
+    fn trace_instruction(&mut self, mnemonic: &'static str, len: usize) {
+        if let Some(tracer) = self.tracer.as_mut() {
+            let (memory, ip) = (&self.memory, self.ip);
+            let cells: Vec<W> = (ip..ip + len).map(|address| memory.read(address)).collect();
+            tracer.before_instruction(ip, mnemonic, &cells, self.relative_base.clone());
+        }
+    }
+
+    fn check_budget(&self) -> Result<(), IntcodeError<W>> {
+        let out_of_instructions = self.budget.max_instructions == Some(0);
+        let out_of_time = match self.budget.deadline {
+            Some(deadline) => {
+                self.instructions_executed.is_multiple_of(DEADLINE_CHECK_INTERVAL)
+                    && Instant::now() >= deadline
+            }
+            None => false,
+        };
+        if out_of_instructions || out_of_time {
+            Err(IntcodeError::BudgetExhausted {
+                ip: self.ip,
+                instruction: self.instruction(),
+                snapshot: Box::new(self.snapshot()),
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Writes to memory on behalf of the running program.
+    fn store(&mut self, address: usize, value: W) {
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.on_write(address, self.memory.read(address), value.clone());
+        }
+        if let Some(history) = self.history.as_mut() {
+            history.record_write(WriteRecord {
+                ip: self.ip,
+                address,
+                old_value: self.memory.read(address),
+                new_value: value.clone(),
+            });
+        }
+        if !self.watchpoints.is_empty() {
+            self.accesses.get_mut().push(MemoryAccess {
+                address,
+                old_value: Some(self.memory.read(address)),
+                new_value: value.clone(),
+            });
+        }
+        self.memory.write(address, value);
+    }
+
+    /// Reads memory on behalf of the running program.
+    fn load(&self, address: usize) -> W {
+        let value = self.memory.read(address);
+        if !self.watchpoints.is_empty() || self.tracer.is_some() {
+            self.accesses.borrow_mut().push(MemoryAccess {
+                address,
+                old_value: None,
+                new_value: value.clone(),
+            });
+        }
+        value
+    }
+
+    fn instruction(&self) -> W {
+        self.memory.read(self.ip)
+    }
+
+    pub(crate) fn input_exhausted(&self) -> IntcodeError<W> {
+        IntcodeError::InputExhausted {
+            ip: self.ip,
+            instruction: self.instruction(),
+        }
+    }
+
+    fn add(&self, operand_1: W, operand_2: W) -> Result<W, IntcodeError<W>> {
+        if !self.strict {
+            return Ok(operand_1.wrapping_add(&operand_2));
+        }
+        operand_1.checked_add(&operand_2).ok_or_else(|| IntcodeError::Overflow {
+            ip: self.ip,
+            instruction: self.instruction(),
+            operand_1,
+            operand_2,
+        })
+    }
+
+    fn multiply(&self, operand_1: W, operand_2: W) -> Result<W, IntcodeError<W>> {
+        if !self.strict {
+            return Ok(operand_1.wrapping_mul(&operand_2));
+        }
+        operand_1.checked_mul(&operand_2).ok_or_else(|| IntcodeError::Overflow {
+            ip: self.ip,
+            instruction: self.instruction(),
+            operand_1,
+            operand_2,
+        })
+    }
+
+    fn jump_target(&self, target: W) -> Result<usize, IntcodeError<W>> {
+        let address = self.check_address(target.clone())?;
+        if self.strict && !self.memory.contains(address) {
+            Err(IntcodeError::JumpOutOfRange {
+                ip: self.ip,
+                instruction: self.instruction(),
+                target,
+            })
+        } else {
+            Ok(address)
+        }
+    }
+
+    fn check_address(&self, address: W) -> Result<usize, IntcodeError<W>> {
+        match address.to_usize() {
+            Some(address) => Ok(address),
+            None if address < W::from_i64(0) => Err(IntcodeError::NegativeAddress {
+                ip: self.ip,
+                instruction: self.instruction(),
+                address,
+            }),
+            None => Err(IntcodeError::AddressTooLarge {
+                ip: self.ip,
+                instruction: self.instruction(),
+                address,
+            }),
+        }
+    }
+
+    /// Gets the raw value of the parameter at the given offset from the current instruction.
+    fn param(&self, offset: usize) -> Result<W, IntcodeError<W>> {
+        Ok(self.memory.read(self.offset_from_ip(offset)?))
+    }
+
+    /// The address at an offset from the instruction pointer, which may be past the last address
+    /// if the program has jumped near the end of memory.
+    fn offset_from_ip(&self, offset: usize) -> Result<usize, IntcodeError<W>> {
+        self.ip.checked_add(offset).ok_or_else(|| IntcodeError::AddressTooLarge {
+            ip: self.ip,
+            instruction: self.instruction(),
+            // Only words wider than usize can jump this far, so they can hold the address
+            address: W::from_u128(self.ip as u128 + offset as u128).unwrap_or_default(),
+        })
+    }
+}
+
+fn get_param_value<W: Word>(
+    mode: ParameterMode,
+    param: W,
+    machine: &Machine<W>,
+) -> Result<W, IntcodeError<W>> {
+    match mode {
+        ParameterMode::Immediate => Ok(param),
+        ParameterMode::Position => Ok(machine.load(machine.check_address(param)?)),
+        ParameterMode::Relative => {
+            let address = machine.add(param, machine.relative_base.clone())?;
+            Ok(machine.load(machine.check_address(address)?))
+        }
+    }
+}
+
+fn get_write_location<W: Word>(
+    mode: ParameterMode,
+    param: W,
+    machine: &Machine<W>,
+) -> Result<usize, IntcodeError<W>> {
+    match mode {
+        ParameterMode::Immediate => Err(IntcodeError::ImmediateWrite {
+            ip: machine.ip,
+            instruction: machine.instruction(),
+        }),
+        ParameterMode::Position => machine.check_address(param),
+        ParameterMode::Relative => {
+            machine.check_address(machine.add(param, machine.relative_base.clone())?)
+        }
+    }
+}
+
+/// The instruction being executed, as seen by an opcode handler. Parameters are numbered from 0.
+pub struct Instruction<'a, W> {
+    machine: &'a mut Machine<W>,
+    opcode: Opcode,
+    mnemonic: &'static str,
+    /// The number of cells in the instruction, including the opcode.
+    len: usize,
+    jumped: bool,
+    /// Whether the tracer has been told about the instruction.
+    started: bool,
+    /// The error to fail with if the instruction does anything, when the budget has run out.
+    out_of_budget: Option<IntcodeError<W>>,
+}
+
+impl<'a, W: Word> Instruction<'a, W> {
+    pub fn ip(&self) -> usize {
+        self.machine.ip
+    }
+
+    /// Tells the tracer about the instruction, the first time this is called.
+    fn trace(&mut self) {
+        if !self.started {
+            self.started = true;
+            self.machine.trace_instruction(self.mnemonic, self.len);
+        }
+    }
+
+    /// Called before the instruction changes anything, once it's clear it isn't halting or
+    /// waiting for input, as that's when it starts using up the budget.
+    fn start(&mut self) -> Result<(), IntcodeError<W>> {
+        if let Some(error) = &self.out_of_budget {
+            return Err(error.clone());
+        }
+        self.trace();
+        Ok(())
+    }
+
+    /// The value of a parameter, following its mode.
+    pub fn param(&self, index: usize) -> Result<W, IntcodeError<W>> {
+        let param = self.machine.param(index + 1)?;
+        get_param_value(self.opcode.param_mode(index), param, self.machine)
+    }
+
+    /// Writes to the address a parameter refers to.
+    pub fn write(&mut self, index: usize, value: W) -> Result<(), IntcodeError<W>> {
+        self.start()?;
+        let param = self.machine.param(index + 1)?;
+        let address = get_write_location(self.opcode.param_mode(index), param, self.machine)?;
+        self.machine.store(address, value);
+        Ok(())
+    }
+
+    /// Continues at the target address rather than the next instruction.
+    pub fn jump(&mut self, target: W) -> Result<(), IntcodeError<W>> {
+        self.start()?;
+        self.machine.ip = self.machine.jump_target(target)?;
+        self.jumped = true;
+        Ok(())
+    }
+
+    /// Takes the next input value. If there isn't one the handler should return
+    /// `Event::NeedsInput`, so the instruction runs again once some input has been pushed.
+    pub fn input(&mut self) -> Option<W> {
+        if self.machine.inputs.is_empty() || self.start().is_err() {
+            return None;
+        }
+        let value = self.machine.inputs.pop_front()?;
+        if let Some(tracer) = self.machine.tracer.as_mut() {
+            tracer.on_input(value.clone());
+        }
+        if let Some(history) = self.machine.history.as_mut() {
+            history.record_input(value.clone());
+        }
+        self.machine.inputs_read += 1;
+        Some(value)
+    }
+
+    pub fn relative_base(&self) -> W {
+        self.machine.relative_base.clone()
+    }
+
+    pub fn set_relative_base(&mut self, relative_base: W) -> Result<(), IntcodeError<W>> {
+        self.start()?;
+        self.machine.relative_base = relative_base;
+        Ok(())
+    }
+
+    /// Adds two values, failing on overflow if the machine is in strict mode.
+    pub fn add(&self, operand_1: W, operand_2: W) -> Result<W, IntcodeError<W>> {
+        self.machine.add(operand_1, operand_2)
+    }
+
+    /// Multiplies two values, failing on overflow if the machine is in strict mode.
+    pub fn multiply(&self, operand_1: W, operand_2: W) -> Result<W, IntcodeError<W>> {
+        self.machine.multiply(operand_1, operand_2)
+    }
+}
+
+pub(crate) fn handle_add<W: Word>(instruction: &mut Instruction<W>) -> HandlerResult<W> {
+    let sum = instruction.add(instruction.param(0)?, instruction.param(1)?)?;
+    instruction.write(2, sum)?;
+    Ok(None)
+}
+
+pub(crate) fn handle_mult<W: Word>(instruction: &mut Instruction<W>) -> HandlerResult<W> {
+    let product = instruction.multiply(instruction.param(0)?, instruction.param(1)?)?;
+    instruction.write(2, product)?;
+    Ok(None)
+}
+
+pub(crate) fn handle_input<W: Word>(instruction: &mut Instruction<W>) -> HandlerResult<W> {
+    match instruction.input() {
+        Some(value) => {
+            instruction.write(0, value)?;
+            Ok(None)
+        }
+        None => Ok(Some(Event::NeedsInput)),
+    }
+}
+
+pub(crate) fn handle_output<W: Word>(instruction: &mut Instruction<W>) -> HandlerResult<W> {
+    Ok(Some(Event::Output(instruction.param(0)?)))
+}
+
+pub(crate) fn handle_jump_if_true<W: Word>(instruction: &mut Instruction<W>) -> HandlerResult<W> {
+    if instruction.param(0)? != W::from_i64(0) {
+        instruction.jump(instruction.param(1)?)?;
+    }
+    Ok(None)
+}
+
+pub(crate) fn handle_jump_if_false<W: Word>(instruction: &mut Instruction<W>) -> HandlerResult<W> {
+    if instruction.param(0)? == W::from_i64(0) {
+        instruction.jump(instruction.param(1)?)?;
+    }
+    Ok(None)
+}
+
+pub(crate) fn handle_less_than<W: Word>(instruction: &mut Instruction<W>) -> HandlerResult<W> {
+    let result = instruction.param(0)? < instruction.param(1)?;
+    instruction.write(2, W::from_i64(result as i64))?;
+    Ok(None)
+}
+
+pub(crate) fn handle_equals<W: Word>(instruction: &mut Instruction<W>) -> HandlerResult<W> {
+    let result = instruction.param(0)? == instruction.param(1)?;
+    instruction.write(2, W::from_i64(result as i64))?;
+    Ok(None)
+}
+
+pub(crate) fn handle_adjust_relative_base<W: Word>(
+    instruction: &mut Instruction<W>,
+) -> HandlerResult<W> {
+    let relative_base = instruction.add(instruction.relative_base(), instruction.param(0)?)?;
+    instruction.set_relative_base(relative_base)?;
+    Ok(None)
+}
+
+pub(crate) fn handle_halt<W: Word>(_instruction: &mut Instruction<W>) -> HandlerResult<W> {
+    Ok(Some(Event::Halted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_program, Coverage};
+
+    #[test]
+    fn test_full_code_parsed_successfully() {
+        let opcode = Opcode::from_string("11101");
+        assert_eq!(opcode.code, 1);
+        assert_eq!(opcode.param_1_mode, ParameterMode::Immediate);
+        assert_eq!(opcode.param_2_mode, ParameterMode::Immediate);
+        assert_eq!(opcode.param_3_mode, ParameterMode::Immediate);
+
+        let opcode = Opcode::from_string("21002");
+        assert_eq!(opcode.code, 2);
+        assert_eq!(opcode.param_1_mode, ParameterMode::Position);
+        assert_eq!(opcode.param_2_mode, ParameterMode::Immediate);
+        assert_eq!(opcode.param_3_mode, ParameterMode::Relative);
+    }
+
+    #[test]
+    fn test_leading_zero_suppression() {
+        let opcode = Opcode::from_string("03");
+        assert_eq!(opcode.code, 3);
+        assert_eq!(opcode.param_1_mode, ParameterMode::Position);
+        assert_eq!(opcode.param_2_mode, ParameterMode::Position);
+        assert_eq!(opcode.param_3_mode, ParameterMode::Position);
+    }
+
+    #[test]
+    fn test_single_digit() {
+        let opcode = Opcode::from_string("3");
+        assert_eq!(opcode.code, 3);
+        assert_eq!(opcode.param_1_mode, ParameterMode::Position);
+        assert_eq!(opcode.param_2_mode, ParameterMode::Position);
+        assert_eq!(opcode.param_3_mode, ParameterMode::Position);
+    }
+
+    #[test]
+    fn test_pauses_for_input_and_output() {
+        // Echoes its input back until it sees a 0
+        let mut machine = Machine::new(parse_program("3,9,4,9,1005,9,0,99,0,0").unwrap());
+        assert_eq!(machine.run_until_event(), Ok(Event::NeedsInput));
+        assert_eq!(machine.run_until_event(), Ok(Event::NeedsInput));
+        machine.push_input(5);
+        machine.push_input(0);
+        assert_eq!(machine.run_until_event(), Ok(Event::Output(5)));
+        assert_eq!(machine.run_until_event(), Ok(Event::Output(0)));
+        assert_eq!(machine.run_until_event(), Ok(Event::Halted));
+        assert_eq!(machine.run_until_event(), Ok(Event::Halted));
+    }
+
+    #[test]
+    fn test_machine_from_literal() {
+        let mut machine = Machine::new(vec![1101, 1, 1, 0, 99]);
+        assert_eq!(machine.run_until_output(), Ok(None));
+        assert_eq!(machine.read(0), 2);
+    }
+
+    #[test]
+    fn test_run_until_output() {
+        let mut machine = Machine::new(parse_program("3,7,4,7,4,7,99,0").unwrap());
+        assert_eq!(
+            machine.run_until_output(),
+            Err(IntcodeError::InputExhausted {
+                ip: 0,
+                instruction: 3
+            })
+        );
+        machine.push_input(12);
+        assert_eq!(machine.run_until_output(), Ok(Some(12)));
+        assert_eq!(machine.run_until_output(), Ok(Some(12)));
+        assert_eq!(machine.run_until_output(), Ok(None));
+    }
+
+    #[test]
+    fn test_instruction_budget() {
+        // Loops forever, counting in cell 7
+        let mut machine = Machine::new(parse_program("101,1,7,7,1105,1,0,0").unwrap());
+        machine.set_budget(Budget::instructions(5));
+        match machine.run_until_event() {
+            Err(IntcodeError::BudgetExhausted {
+                ip,
+                snapshot,
+                ..
+            }) => {
+                assert_eq!(ip, 4);
+                assert_eq!(Machine::restore(*snapshot).read(7), 3);
+            }
+            other => panic!("Expected the budget to run out, got {:?}", other),
+        }
+        assert_eq!(machine.instructions_executed(), 5);
+
+        // A fresh budget picks up where the last one left off
+        machine.set_budget(Budget::instructions(2));
+        assert!(machine.run_until_event().is_err());
+        assert_eq!(machine.read(7), 4);
+    }
+
+    #[test]
+    fn test_deadline() {
+        let mut machine = Machine::new(parse_program("1105,1,0").unwrap());
+        machine.set_budget(Budget::timeout(Duration::from_millis(10)));
+        match machine.run_until_event() {
+            Err(IntcodeError::BudgetExhausted {
+                ip: 0,
+                ..
+            }) => (),
+            other => panic!("Expected the deadline to pass, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_halt_does_not_use_budget() {
+        let mut machine = Machine::new(parse_program("99").unwrap());
+        machine.set_budget(Budget::instructions(0));
+        assert_eq!(machine.run_until_event(), Ok(Event::Halted));
+    }
+
+    #[test]
+    fn test_arithmetic_wraps_outside_strict_mode() {
+        let mut machine =
+            Machine::new(parse_program("1102,4611686018427387904,4,7,4,7,99,0").unwrap());
+        assert_eq!(machine.run_until_event(), Ok(Event::Output(0)));
+    }
+
+    #[test]
+    fn test_instruction_at_end_of_memory() {
+        let mut machine = Machine::<i128>::from_words(vec![1105, 1, 18446744073709551614]);
+        machine.write(18446744073709551614, 104);
+        assert_eq!(
+            machine.run_until_event(),
+            Err(IntcodeError::AddressTooLarge {
+                ip: 18446744073709551614,
+                instruction: 104,
+                address: 18446744073709551616,
+            })
+        );
+    }
+
+    #[test]
+    fn test_halt_is_traced_once() {
+        let coverage = Rc::new(RefCell::new(Coverage::new()));
+        let mut machine = Machine::new(parse_program("104,1,99").unwrap());
+        machine.set_tracer(Box::new(Rc::clone(&coverage)));
+        assert_eq!(machine.run_until_event(), Ok(Event::Output(1)));
+        assert_eq!(machine.run_until_event(), Ok(Event::Halted));
+        assert_eq!(machine.run_until_event(), Ok(Event::Halted));
+        assert_eq!(coverage.borrow().execution_count(2), 1);
+        assert_eq!(machine.instructions_executed(), 1);
+    }
+
+    #[test]
+    fn test_strict_mode() {
+        let run_strict = |source| {
+            let mut machine = Machine::new(parse_program(source).unwrap());
+            machine.set_strict(true);
+            machine.run_until_event()
+        };
+        assert_eq!(
+            run_strict("1102,4611686018427387904,4,7,4,7,99,0"),
+            Err(IntcodeError::Overflow {
+                ip: 0,
+                instruction: 1102,
+                operand_1: 4611686018427387904,
+                operand_2: 4,
+            })
+        );
+        assert_eq!(
+            run_strict("109,9223372036854775807,22201,1,1,0"),
+            Err(IntcodeError::Overflow {
+                ip: 2,
+                instruction: 22201,
+                operand_1: 1,
+                operand_2: 9223372036854775807,
+            })
+        );
+        assert_eq!(
+            run_strict("1105,1,100"),
+            Err(IntcodeError::JumpOutOfRange {
+                ip: 0,
+                instruction: 1105,
+                target: 100,
+            })
+        );
+        assert_eq!(run_strict("1105,1,3,104,7,99"), Ok(Event::Output(7)));
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+/// Addresses below this are stored in a Vec which grows as needed; anything above it goes in a
+/// map so that a single write to a huge address doesn't allocate gigabytes.
+const DENSE_LIMIT: usize = 1 << 16;
+
+/// Intcode memory, which is conceptually infinite and initialized to 0 beyond the program.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Memory<W = i64> {
+    dense: Vec<W>,
+    sparse: HashMap<usize, W>,
+}
+
+impl<W: Clone + Default> Memory<W> {
+    pub(crate) fn new(program: Vec<W>) -> Memory<W> {
+        Memory {
+            dense: program,
+            sparse: HashMap::new(),
+        }
+    }
+
+    /// Rebuilds memory from the pieces returned by dense() and sparse().
+    pub(crate) fn from_parts(dense: Vec<W>, sparse: HashMap<usize, W>) -> Memory<W> {
+        Memory {
+            dense,
+            sparse,
+        }
+    }
+
+    pub(crate) fn dense(&self) -> &[W] {
+        &self.dense
+    }
+
+    pub(crate) fn sparse(&self) -> &HashMap<usize, W> {
+        &self.sparse
+    }
+
+    /// Whether the address is part of the program or has been written to since.
+    pub(crate) fn contains(&self, address: usize) -> bool {
+        address < self.dense.len() || self.sparse.contains_key(&address)
+    }
+
+    pub(crate) fn read(&self, address: usize) -> W {
+        match self.dense.get(address) {
+            Some(value) => value.clone(),
+            None => self.sparse.get(&address).cloned().unwrap_or_default(),
+        }
+    }
+
+    pub(crate) fn write(&mut self, address: usize, value: W) {
+        if address < self.dense.len() {
+            self.dense[address] = value;
+        } else if address < DENSE_LIMIT {
+            self.dense.resize(address + 1, W::default());
+            self.dense[address] = value;
+        } else {
+            self.sparse.insert(address, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unwritten_memory_is_zero() {
+        let memory = Memory::new(vec![1i64, 2, 3]);
+        assert_eq!(memory.read(2), 3);
+        assert_eq!(memory.read(3), 0);
+        assert_eq!(memory.read(usize::MAX), 0);
+    }
+
+    #[test]
+    fn test_memory_grows_on_write() {
+        let mut memory = Memory::new(vec![1i64, 2, 3]);
+        memory.write(100, 7);
+        memory.write(DENSE_LIMIT * 4, 8);
+        assert_eq!(memory.read(100), 7);
+        assert_eq!(memory.read(99), 0);
+        assert_eq!(memory.read(DENSE_LIMIT * 4), 8);
+        assert_eq!(memory.dense.len(), 101);
+        assert_eq!(memory.sparse.len(), 1);
+    }
+}
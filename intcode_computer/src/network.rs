@@ -0,0 +1,270 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::machine::{Event, Machine};
+use crate::IntcodeError;
+
+/// What a machine in a network is doing once the network has stopped running.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MachineStatus {
+    /// Waiting for input which hasn't arrived yet.
+    Blocked,
+    Halted,
+}
+
+/// A machine left waiting for input when its network can't make any more progress.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockedMachine {
+    pub index: usize,
+    pub ip: usize,
+    /// Which input the machine is waiting for, counting from 1.
+    pub input_number: u64,
+    /// The machines linked to this one, none of which will send it anything more.
+    pub sources: Vec<usize>,
+}
+
+impl fmt::Display for BlockedMachine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "machine {} at position {} is waiting for input {}",
+            self.index, self.ip, self.input_number
+        )?;
+        match self.sources.as_slice() {
+            [] => write!(f, " from outside the network"),
+            sources => write!(f, " from machines {:?}", sources),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetworkError {
+    /// One of the machines failed. Holds the machine's index and its error.
+    Machine(usize, IntcodeError),
+    /// Every machine which hasn't halted is waiting for input that can never arrive.
+    Deadlock(Vec<BlockedMachine>),
+    /// The network finished without the given machine producing the output it was run for.
+    NoOutput(usize),
+    /// There were no machines to run, such as an amplifier pipeline with no phase settings.
+    NoMachines,
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::Machine(index, why) => write!(f, "Machine {} failed: {}", index, why),
+            NetworkError::Deadlock(blocked) => {
+                let reports: Vec<String> =
+                    blocked.iter().map(|machine| machine.to_string()).collect();
+                write!(f, "Deadlock: {}", reports.join("; "))
+            }
+            NetworkError::NoOutput(index) => {
+                write!(f, "Machine {} halted without producing any output", index)
+            }
+            NetworkError::NoMachines => write!(f, "There are no machines to run"),
+        }
+    }
+}
+
+impl Error for NetworkError {}
+
+/// A set of machines whose outputs are fed to each other's inputs, run cooperatively on a single
+/// thread.
+///
+/// Each machine runs until it needs input it doesn't have or halts, then the next one gets a turn.
+/// Every output is recorded against the machine that produced it and also pushed to the input of
+/// each machine it's linked to.
+#[derive(Default)]
+pub struct Network {
+    machines: Vec<Machine>,
+    links: Vec<Vec<usize>>,
+    outputs: Vec<Vec<i64>>,
+}
+
+impl Network {
+    pub fn new() -> Network {
+        Network::default()
+    }
+
+    /// Adds a machine to the network, returning its index.
+    pub fn add_machine(&mut self, machine: Machine) -> usize {
+        self.machines.push(machine);
+        self.links.push(Vec::new());
+        self.outputs.push(Vec::new());
+        self.machines.len() - 1
+    }
+
+    /// Sends every output of machine `from` to the input of machine `to`. A machine can be linked
+    /// to several others, in which case each of them gets a copy.
+    pub fn link(&mut self, from: usize, to: usize) {
+        assert!(to < self.machines.len(), "No machine {} in the network", to);
+        self.links[from].push(to);
+    }
+
+    pub fn len(&self) -> usize {
+        self.machines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.machines.is_empty()
+    }
+
+    pub fn machine(&self, index: usize) -> &Machine {
+        &self.machines[index]
+    }
+
+    pub fn machine_mut(&mut self, index: usize) -> &mut Machine {
+        &mut self.machines[index]
+    }
+
+    pub fn push_input(&mut self, index: usize, value: i64) {
+        self.machines[index].push_input(value);
+    }
+
+    /// Everything machine `index` has output so far.
+    pub fn outputs(&self, index: usize) -> &[i64] {
+        &self.outputs[index]
+    }
+
+    /// Runs the machines in turn until none of them can make progress, returning what each one is
+    /// doing at that point. Input can then be pushed to blocked machines and the network run again.
+    pub fn run(&mut self) -> Result<Vec<MachineStatus>, NetworkError> {
+        let mut statuses = vec![MachineStatus::Blocked; self.machines.len()];
+        loop {
+            // Only outputs can unblock a machine, so once a full round produces none we're done
+            let mut produced_output = false;
+            for (index, status) in statuses.iter_mut().enumerate() {
+                *status = loop {
+                    let event = self.machines[index]
+                        .run_until_event()
+                        .map_err(|why| NetworkError::Machine(index, why))?;
+                    match event {
+                        Event::Output(value) => {
+                            produced_output = true;
+                            self.route(index, value);
+                        }
+                        Event::NeedsInput => break MachineStatus::Blocked,
+                        Event::Halted => break MachineStatus::Halted,
+                        Event::Watchpoint(_) => (),
+                    }
+                };
+            }
+            if !produced_output {
+                return Ok(statuses);
+            }
+        }
+    }
+
+    /// Runs the network until every machine has halted. If the machines stop making progress
+    /// before then, fails with a report of each one that's still waiting for input.
+    pub fn run_to_completion(&mut self) -> Result<(), NetworkError> {
+        let statuses = self.run()?;
+        let blocked: Vec<BlockedMachine> = statuses
+            .iter()
+            .enumerate()
+            .filter(|(_, status)| **status == MachineStatus::Blocked)
+            .map(|(index, _)| BlockedMachine {
+                index,
+                ip: self.machines[index].ip(),
+                input_number: self.machines[index].inputs_read() + 1,
+                sources: (0..self.machines.len())
+                    .filter(|from| self.links[*from].contains(&index))
+                    .collect(),
+            })
+            .collect();
+        if blocked.is_empty() {
+            Ok(())
+        } else {
+            Err(NetworkError::Deadlock(blocked))
+        }
+    }
+
+    fn route(&mut self, from: usize, value: i64) {
+        self.outputs[from].push(value);
+        for &to in &self.links[from] {
+            self.machines[to].push_input(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_program;
+
+    /// Reads a value, outputs it doubled and repeats.
+    const DOUBLER: &str = "3,11,102,2,11,11,4,11,1105,1,0,0";
+
+    #[test]
+    fn test_chain() {
+        let mut network = Network::new();
+        let first = network.add_machine(Machine::new(parse_program(DOUBLER).unwrap()));
+        let second = network.add_machine(Machine::new(parse_program(DOUBLER).unwrap()));
+        network.link(first, second);
+        network.push_input(first, 3);
+        network.push_input(first, 5);
+
+        let statuses = network.run().unwrap();
+        assert_eq!(statuses, vec![MachineStatus::Blocked, MachineStatus::Blocked]);
+        assert_eq!(network.outputs(first), &[6, 10]);
+        assert_eq!(network.outputs(second), &[12, 20]);
+
+        network.push_input(first, 1);
+        network.run().unwrap();
+        assert_eq!(network.outputs(second), &[12, 20, 4]);
+    }
+
+    #[test]
+    fn test_machine_errors_name_the_machine() {
+        let mut network = Network::new();
+        network.add_machine(Machine::new(parse_program("99").unwrap()));
+        network.add_machine(Machine::new(parse_program("42").unwrap()));
+        match network.run() {
+            Err(NetworkError::Machine(
+                1,
+                IntcodeError::UnknownOpcode {
+                    ..
+                },
+            )) => (),
+            other => panic!("Expected machine 1 to fail, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_deadlock_report() {
+        // The adder wants two values but the doubler only ever gets one to pass on
+        let mut network = Network::new();
+        let echo = network.add_machine(Machine::new(parse_program("3,5,4,5,99,0").unwrap()));
+        let doubler = network.add_machine(Machine::new(parse_program(DOUBLER).unwrap()));
+        let adder = network.add_machine(Machine::new(
+            parse_program("3,11,3,12,1,11,12,13,4,13,99,0,0,0").unwrap(),
+        ));
+        network.link(echo, doubler);
+        network.link(doubler, adder);
+        network.push_input(echo, 5);
+
+        let error = network.run_to_completion().unwrap_err();
+        assert_eq!(
+            error,
+            NetworkError::Deadlock(vec![
+                BlockedMachine {
+                    index: doubler,
+                    ip: 0,
+                    input_number: 2,
+                    sources: vec![echo],
+                },
+                BlockedMachine {
+                    index: adder,
+                    ip: 2,
+                    input_number: 2,
+                    sources: vec![doubler],
+                },
+            ])
+        );
+        assert_eq!(
+            error.to_string(),
+            "Deadlock: machine 1 at position 0 is waiting for input 2 from machines [0]; \
+             machine 2 at position 2 is waiting for input 2 from machines [1]"
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use std::fmt;
+use std::rc::Rc;
+
+use crate::machine::{
+    handle_add, handle_adjust_relative_base, handle_equals, handle_halt, handle_input,
+    handle_jump_if_false, handle_jump_if_true, handle_less_than, handle_mult, handle_output, Event,
+    Instruction,
+};
+use crate::word::Word;
+use crate::IntcodeError;
+
+/// Opcodes are the last two digits of an instruction, so there can only be this many.
+const MAX_OPCODES: u32 = 100;
+
+/// The most parameters an opcode can take, since an instruction only has three mode digits.
+const MAX_PARAMS: usize = 3;
+
+/// How an opcode uses each of its parameters, which decides the modes the parameter may be in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParamKind {
+    /// A value to read, in any mode.
+    Read,
+    /// An address to write to, so it can't be in immediate mode.
+    Write,
+}
+
+/// Where an opcode sends control after it runs, so the program can be analysed without running it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControlFlow {
+    /// Always moves on to the next instruction.
+    Next,
+    /// Stops the program.
+    Halt,
+    /// Jumps to its second parameter if its first is non-zero, and otherwise moves on.
+    JumpIfTrue,
+    /// Jumps to its second parameter if its first is zero, and otherwise moves on.
+    JumpIfFalse,
+    /// May move on or jump anywhere, which can't be worked out without running it.
+    Unknown,
+}
+
+/// What an opcode handler returns: an event for the caller, if the instruction caused one.
+pub type HandlerResult<W = i64> = Result<Option<Event<W>>, IntcodeError<W>>;
+
+type Handler<W> = Rc<dyn Fn(&mut Instruction<W>) -> HandlerResult<W>>;
+
+/// What the assembler, disassembler and static analyses need to know about an opcode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct OpcodeInfo {
+    pub(crate) code: u32,
+    pub(crate) mnemonic: &'static str,
+    pub(crate) params: &'static [ParamKind],
+    pub(crate) flow: ControlFlow,
+}
+
+impl OpcodeInfo {
+    pub(crate) fn param_count(&self) -> usize {
+        self.params.len()
+    }
+
+    /// Whether the zero-based parameter is an address the opcode writes to.
+    pub(crate) fn writes(&self, index: usize) -> bool {
+        self.params[index] == ParamKind::Write
+    }
+}
+
+pub(crate) struct OpcodeDefinition<W> {
+    pub(crate) mnemonic: &'static str,
+    pub(crate) params: &'static [ParamKind],
+    pub(crate) flow: ControlFlow,
+    pub(crate) handler: Handler<W>,
+}
+
+impl<W> Clone for OpcodeDefinition<W> {
+    fn clone(&self) -> Self {
+        OpcodeDefinition {
+            mnemonic: self.mnemonic,
+            params: self.params,
+            flow: self.flow,
+            handler: Rc::clone(&self.handler),
+        }
+    }
+}
+
+/// The set of opcodes a machine understands, and what each of them does.
+///
+/// Start from `OpcodeRegistry::standard()` and define extra opcodes or replace existing ones,
+/// then give the registry to a machine with `Machine::set_opcodes`. After a handler returns, the
+/// machine moves on to the next instruction unless the handler jumped, or returned
+/// `Event::NeedsInput` or `Event::Halted` to leave the machine where it is. Those two don't count
+/// as executing an instruction, so a handler returning them shouldn't have changed anything.
+#[derive(Clone)]
+pub struct OpcodeRegistry<W = i64> {
+    definitions: Vec<Option<OpcodeDefinition<W>>>,
+}
+
+impl<W: Word> OpcodeRegistry<W> {
+    /// A registry with no opcodes at all.
+    pub fn empty() -> OpcodeRegistry<W> {
+        OpcodeRegistry {
+            definitions: (0..MAX_OPCODES).map(|_| None).collect(),
+        }
+    }
+
+    /// The opcodes of the full Intcode instruction set.
+    pub fn standard() -> OpcodeRegistry<W> {
+        use ControlFlow::{Halt, JumpIfFalse, JumpIfTrue, Next};
+        use ParamKind::{Read, Write};
+
+        let mut registry = OpcodeRegistry::empty();
+        registry.define(1, "ADD", &[Read, Read, Write], Next, handle_add);
+        registry.define(2, "MUL", &[Read, Read, Write], Next, handle_mult);
+        registry.define(3, "IN", &[Write], Next, handle_input);
+        registry.define(4, "OUT", &[Read], Next, handle_output);
+        registry.define(5, "JT", &[Read, Read], JumpIfTrue, handle_jump_if_true);
+        registry.define(6, "JF", &[Read, Read], JumpIfFalse, handle_jump_if_false);
+        registry.define(7, "LT", &[Read, Read, Write], Next, handle_less_than);
+        registry.define(8, "EQ", &[Read, Read, Write], Next, handle_equals);
+        registry.define(9, "ARB", &[Read], Next, handle_adjust_relative_base);
+        registry.define(99, "HLT", &[], Halt, handle_halt);
+        registry
+    }
+
+    /// Defines an opcode, replacing any existing definition for the same code. The control flow
+    /// should match what the handler does, as that's what static analyses go on.
+    pub fn define(
+        &mut self,
+        code: u32,
+        mnemonic: &'static str,
+        params: &'static [ParamKind],
+        flow: ControlFlow,
+        handler: impl Fn(&mut Instruction<W>) -> HandlerResult<W> + 'static,
+    ) {
+        assert!(code < MAX_OPCODES, "Opcode {} doesn't fit in two digits", code);
+        assert!(params.len() <= MAX_PARAMS, "Opcodes can't take more than 3 parameters");
+        let conditional = flow == ControlFlow::JumpIfTrue || flow == ControlFlow::JumpIfFalse;
+        assert!(!conditional || params.len() >= 2, "Conditional jumps take a condition and target");
+        self.definitions[code as usize] = Some(OpcodeDefinition {
+            mnemonic,
+            params,
+            flow,
+            handler: Rc::new(handler),
+        });
+    }
+
+    /// Removes an opcode, returning whether it was defined.
+    pub fn remove(&mut self, code: u32) -> bool {
+        match self.definitions.get_mut(code as usize) {
+            Some(definition) => definition.take().is_some(),
+            None => false,
+        }
+    }
+
+    pub fn contains(&self, code: u32) -> bool {
+        self.get(code).is_some()
+    }
+
+    pub fn mnemonic(&self, code: u32) -> Option<&'static str> {
+        self.get(code).map(|definition| definition.mnemonic)
+    }
+
+    /// Looks up an opcode by its mnemonic, ignoring case, e.g. "ADD" or "hlt".
+    pub fn code_for(&self, mnemonic: &str) -> Option<u32> {
+        (0..MAX_OPCODES).find(|&code| {
+            self.mnemonic(code).is_some_and(|name| name.eq_ignore_ascii_case(mnemonic))
+        })
+    }
+
+    pub(crate) fn info(&self, code: u32) -> Option<OpcodeInfo> {
+        self.get(code).map(|definition| OpcodeInfo {
+            code,
+            mnemonic: definition.mnemonic,
+            params: definition.params,
+            flow: definition.flow,
+        })
+    }
+
+    pub(crate) fn get(&self, code: u32) -> Option<&OpcodeDefinition<W>> {
+        self.definitions.get(code as usize)?.as_ref()
+    }
+}
+
+impl<W: Word> Default for OpcodeRegistry<W> {
+    fn default() -> Self {
+        OpcodeRegistry::standard()
+    }
+}
+
+impl<W> fmt::Debug for OpcodeRegistry<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mnemonics = self.definitions.iter().enumerate().filter_map(|(code, definition)| {
+            definition.as_ref().map(|definition| (code, definition.mnemonic))
+        });
+        f.debug_map().entries(mnemonics).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{disassemble, parse_program, CellCoverage, Coverage, Machine, Profile};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use ControlFlow::Next;
+    use ParamKind::{Read, Write};
+
+    #[test]
+    fn test_custom_opcodes() {
+        let mut registry = OpcodeRegistry::standard();
+        // SWP a, b swaps two cells
+        registry.define(10, "SWP", &[Write, Write], Next, |instruction| {
+            let (a, b) = (instruction.param(0)?, instruction.param(1)?);
+            instruction.write(0, b)?;
+            instruction.write(1, a)?;
+            Ok(None)
+        });
+        // Outputs double the value instead
+        registry.define(4, "OUT", &[Read], Next, |instruction| {
+            let value = instruction.param(0)?;
+            Ok(Some(Event::Output(value * 2)))
+        });
+
+        let program = parse_program("10,7,8,4,7,99,0,5,6").unwrap();
+        assert!(disassemble(&program, &registry).starts_with("    0: SWP [7], [8]\n"));
+        assert_eq!(registry.code_for("swp"), Some(10));
+        let coverage = Rc::new(RefCell::new(Coverage::new()));
+        let mut machine = Machine::new(program);
+        machine.set_opcodes(registry.clone());
+        machine.set_profile(Profile::Full);
+        machine.set_tracer(Box::new(Rc::clone(&coverage)));
+        assert_eq!(machine.run_until_output(), Ok(Some(12)));
+        assert_eq!(machine.read(8), 5);
+        assert_eq!(machine.run_until_output(), Ok(None));
+        // Tracers are told how long each instruction is by the machine
+        assert_eq!(coverage.borrow().status(2), CellCoverage::Executed);
+
+        registry.remove(10);
+        let mut machine = Machine::new(parse_program("10,7,8,4,7,99,0,5,6").unwrap());
+        machine.set_opcodes(registry);
+        assert_eq!(
+            machine.run_until_output(),
+            Err(IntcodeError::UnknownOpcode {
+                ip: 0,
+                instruction: 10
+            })
+        );
+    }
+
+    #[test]
+    fn test_overriding_input_and_halt() {
+        let mut registry = OpcodeRegistry::standard();
+        // Reads -1 when there's no input rather than waiting
+        registry.define(3, "IN", &[Write], Next, |instruction| {
+            let value = instruction.input().unwrap_or(-1);
+            instruction.write(0, value)?;
+            Ok(None)
+        });
+        registry.define(99, "NOP", &[], Next, |_| Ok(None));
+        registry.define(50, "WAIT", &[], Next, |_| Ok(Some(Event::NeedsInput)));
+
+        let mut machine = Machine::new(parse_program("3,7,99,4,7,50,0,0").unwrap());
+        machine.set_opcodes(registry);
+        machine.record_history(10);
+        assert_eq!(machine.run_until_event(), Ok(Event::Output(-1)));
+        for _ in 0..3 {
+            assert_eq!(machine.run_until_event(), Ok(Event::NeedsInput));
+        }
+        assert_eq!(machine.ip(), 5);
+        assert_eq!(machine.instructions_executed(), 3);
+        assert_eq!(machine.history_len(), 3);
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::cfg::{decode, flow};
+use crate::machine::{Opcode, ParameterMode};
+use crate::opcodes::{OpcodeInfo, OpcodeRegistry};
+use crate::word::Word;
+
+/// A subset of the Intcode instruction set, named after the puzzle which introduced it.
+///
+/// Profiles are ordered, so each one includes everything in the profiles before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Profile {
+    /// Add, multiply and halt, with position mode parameters only.
+    Day2,
+    /// Adds input, output, jumps and comparisons, and immediate mode parameters.
+    Day5,
+    /// The full instruction set, with relative mode and the adjust relative base instruction, as
+    /// well as any custom opcodes.
+    Full,
+}
+
+impl Profile {
+    fn required_for_mode(mode: ParameterMode) -> Profile {
+        match mode {
+            ParameterMode::Position => Profile::Day2,
+            ParameterMode::Immediate => Profile::Day5,
+            ParameterMode::Relative => Profile::Full,
+        }
+    }
+
+    /// The smallest profile which supports an instruction.
+    fn required_for(opcode: &Opcode, info: &OpcodeInfo) -> Profile {
+        let code_profile = match info.code {
+            1 | 2 | 99 => Profile::Day2,
+            3..=8 => Profile::Day5,
+            _ => Profile::Full,
+        };
+        (0..info.param_count())
+            .map(|index| Profile::required_for_mode(opcode.param_mode(index)))
+            .fold(code_profile, Profile::max)
+    }
+
+    pub(crate) fn allows<W: Word>(self, opcode: &Opcode, opcodes: &OpcodeRegistry<W>) -> bool {
+        match opcodes.info(opcode.code) {
+            Some(info) => Profile::required_for(opcode, &info) <= self,
+            None => false,
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Profile::Day2 => "day2",
+            Profile::Day5 => "day5",
+            Profile::Full => "full",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "day2" => Ok(Profile::Day2),
+            "day5" => Ok(Profile::Day5),
+            "day9" | "full" => Ok(Profile::Full),
+            _ => Err(format!("Unknown profile '{}', expected day2, day5, day9 or full", name)),
+        }
+    }
+}
+
+/// What a static scan of a program found it needs in order to run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Validation {
+    /// The smallest profile which supports every instruction found.
+    pub profile: Profile,
+    /// The address of the first instruction found which needs that profile, unless it's Day2.
+    pub needed_by: Option<usize>,
+    /// Addresses which are reached but don't hold a valid instruction.
+    pub invalid: Vec<usize>,
+    /// Addresses of jumps whose targets aren't known until the program runs. Code which is only
+    /// reached through them isn't scanned.
+    pub unresolved_jumps: Vec<usize>,
+}
+
+/// Scans a program without running it, following every path from address 0, to find out which
+/// profile it needs.
+pub fn validate_program(program: &[i64], opcodes: &OpcodeRegistry) -> Validation {
+    let mut validation = Validation {
+        profile: Profile::Day2,
+        needed_by: None,
+        invalid: Vec::new(),
+        unresolved_jumps: Vec::new(),
+    };
+    let mut seen = HashSet::new();
+    let mut pending = vec![0];
+    while let Some(address) = pending.pop() {
+        // Running off the end of the program can't be checked statically
+        if address >= program.len() || !seen.insert(address) {
+            continue;
+        }
+        let (opcode, info) = match decode(program, address, opcodes) {
+            Some(decoded) => decoded,
+            None => {
+                validation.invalid.push(address);
+                continue;
+            }
+        };
+
+        let profile = Profile::required_for(&opcode, &info);
+        if profile > validation.profile {
+            validation.profile = profile;
+            validation.needed_by = Some(address);
+        }
+
+        let flow = flow(program, address, &opcode, &info);
+        pending.extend(flow.successors);
+        if flow.indirect {
+            validation.unresolved_jumps.push(address);
+        }
+    }
+    validation.invalid.sort_unstable();
+    validation.unresolved_jumps.sort_unstable();
+    validation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_program, IntcodeError, Machine};
+
+    fn validate(source: &str) -> Validation {
+        validate_program(&parse_program(source).unwrap(), &OpcodeRegistry::standard())
+    }
+
+    #[test]
+    fn test_validate_program() {
+        // The data after the halt isn't scanned
+        let validation = validate("1,9,10,3,2,3,11,0,99,30,40,50");
+        assert_eq!(validation.profile, Profile::Day2);
+        assert_eq!(validation.needed_by, None);
+        assert!(validation.invalid.is_empty());
+
+        // Only the taken side of an unconditional jump is followed
+        let validation = validate("1105,1,4,109,3,0,4,0,99");
+        assert_eq!(validation.profile, Profile::Day5);
+        assert_eq!(validation.needed_by, Some(0));
+        assert_eq!(validation.invalid, vec![]);
+
+        let validation = validate("3,9,1005,9,7,99,0,209,0,0");
+        assert_eq!(validation.profile, Profile::Full);
+        assert_eq!(validation.needed_by, Some(7));
+        assert_eq!(validation.invalid, vec![9]);
+
+        let validation = validate("3,9,1006,9,7,4,9,99,0,0");
+        assert_eq!(validation.profile, Profile::Day5);
+        assert_eq!(validation.unresolved_jumps, vec![]);
+        let validation = validate("3,9,6,9,8,4,9,99,7,0");
+        assert_eq!(validation.unresolved_jumps, vec![2]);
+    }
+
+    #[test]
+    fn test_machine_enforces_profile() {
+        let mut machine = Machine::new(parse_program("1101,2,3,5,99,0").unwrap());
+        machine.set_profile(Profile::Day2);
+        assert_eq!(
+            machine.run_until_output(),
+            Err(IntcodeError::NotInProfile {
+                ip: 0,
+                instruction: 1101,
+                profile: Profile::Day2,
+            })
+        );
+
+        machine.set_profile(Profile::Day5);
+        assert_eq!(machine.run_until_output(), Ok(None));
+        assert_eq!(machine.read(5), 5);
+
+        // An opcode which doesn't exist at all isn't reported as missing from the profile
+        let mut machine = Machine::new(vec![42i64]);
+        machine.set_profile(Profile::Day2);
+        assert_eq!(
+            machine.run_until_output(),
+            Err(IntcodeError::UnknownOpcode {
+                ip: 0,
+                instruction: 42,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_profile() {
+        assert_eq!("day9".parse(), Ok(Profile::Full));
+        assert_eq!(Profile::Day5.to_string().parse(), Ok(Profile::Day5));
+        assert!("day7".parse::<Profile>().is_err());
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::hash::Hash;
+use std::io;
+use std::io::prelude::*;
+
+use crate::machine::{Opcode, ParameterMode};
+use crate::trace::Tracer;
+
+/// A tracer which counts where a program spends its time: how often each opcode, address and
+/// parameter mode is executed and which jumps are taken.
+///
+/// It also keeps a call stack for a flamegraph. Intcode has no calls, but compiled programs grow
+/// the relative base on entering a function and shrink it again on leaving, so each increase
+/// pushes a frame named after the instruction which made it, and a decrease pops every frame that
+/// was pushed above the new base.
+#[derive(Debug, Default)]
+pub struct Profiler {
+    instructions: u64,
+    opcodes: HashMap<u32, u64>,
+    /// The mnemonic each opcode ran as, to name it in the report.
+    mnemonics: HashMap<u32, &'static str>,
+    addresses: HashMap<usize, u64>,
+    modes: HashMap<ParameterMode, u64>,
+    jumps: HashMap<(usize, usize), u64>,
+    stacks: HashMap<Vec<usize>, u64>,
+    /// The address of the instruction which pushed each frame, and the relative base before it.
+    frames: Vec<usize>,
+    frame_bases: Vec<i64>,
+    current: Option<Current>,
+}
+
+/// The instruction which is running.
+#[derive(Debug)]
+struct Current {
+    ip: usize,
+    relative_base: i64,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// How many times an opcode was executed.
+    pub fn opcode_count(&self, code: u32) -> u64 {
+        self.opcodes.get(&code).copied().unwrap_or(0)
+    }
+
+    /// How many times the instruction at an address was executed.
+    pub fn address_count(&self, address: usize) -> u64 {
+        self.addresses.get(&address).copied().unwrap_or(0)
+    }
+
+    /// The jumps taken, as (from, to) addresses with a count, most frequent first.
+    pub fn hottest_jumps(&self) -> Vec<((usize, usize), u64)> {
+        sorted(&self.jumps)
+    }
+
+    /// A report of the counts, most frequent first, listing at most `limit` addresses and jumps.
+    pub fn report(&self, limit: usize) -> String {
+        let mut report = String::new();
+        let percent = |count: u64| 100.0 * count as f64 / self.instructions.max(1) as f64;
+        let _ = writeln!(report, "Instructions executed: {}", self.instructions);
+
+        let _ = writeln!(report, "\nOpcodes:");
+        for (code, count) in sorted(&self.opcodes) {
+            let name = self.mnemonics[&code];
+            let _ = writeln!(report, "  {:<6} {:>12} {:>6.2}%", name, count, percent(count));
+        }
+
+        let _ = writeln!(report, "\nParameter modes:");
+        for (mode, count) in sorted(&self.modes) {
+            let name = match mode {
+                ParameterMode::Position => "position",
+                ParameterMode::Immediate => "immediate",
+                ParameterMode::Relative => "relative",
+            };
+            let _ = writeln!(report, "  {:<9} {:>12}", name, count);
+        }
+
+        let _ = writeln!(report, "\nHottest addresses:");
+        for (address, count) in sorted(&self.addresses).into_iter().take(limit) {
+            let _ = writeln!(report, "  {:>6} {:>12} {:>6.2}%", address, count, percent(count));
+        }
+
+        let _ = writeln!(report, "\nHottest jumps:");
+        for ((from, to), count) in sorted(&self.jumps).into_iter().take(limit) {
+            let _ = writeln!(report, "  {:>6} -> {:<6} {:>12}", from, to, count);
+        }
+        report
+    }
+
+    /// Writes the samples as folded stacks, one line per stack with its instruction count, for
+    /// flamegraph tools.
+    pub fn write_folded(&self, mut writer: impl Write) -> io::Result<()> {
+        for (stack, count) in sorted(&self.stacks) {
+            let mut line = String::from("main");
+            for address in stack {
+                let _ = write!(line, ";frame@{}", address);
+            }
+            writeln!(writer, "{} {}", line, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sorts counts with the largest first, then by key so the order is stable.
+fn sorted<K: Clone + Ord + Hash>(counts: &HashMap<K, u64>) -> Vec<(K, u64)> {
+    let mut counts: Vec<(K, u64)> =
+        counts.iter().map(|(key, count)| (key.clone(), *count)).collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
+
+impl Tracer for Profiler {
+    fn before_instruction(
+        &mut self,
+        ip: usize,
+        mnemonic: &'static str,
+        cells: &[i64],
+        relative_base: i64,
+    ) {
+        self.instructions += 1;
+        *self.addresses.entry(ip).or_insert(0) += 1;
+        match self.stacks.get_mut(&self.frames) {
+            Some(count) => *count += 1,
+            None => {
+                self.stacks.insert(self.frames.clone(), 1);
+            }
+        }
+
+        if let Some(opcode) = Opcode::from_value(cells[0]) {
+            *self.opcodes.entry(opcode.code).or_insert(0) += 1;
+            self.mnemonics.insert(opcode.code, mnemonic);
+            for index in 0..cells.len() - 1 {
+                *self.modes.entry(opcode.param_mode(index)).or_insert(0) += 1;
+            }
+        }
+        self.current = Some(Current {
+            ip,
+            relative_base,
+        });
+    }
+
+    fn after_instruction(&mut self, ip: usize, relative_base: i64, jumped: bool) {
+        let current = match self.current.take() {
+            Some(current) => current,
+            None => return,
+        };
+        if jumped {
+            *self.jumps.entry((current.ip, ip)).or_insert(0) += 1;
+        }
+        if relative_base > current.relative_base {
+            self.frames.push(current.ip);
+            self.frame_bases.push(current.relative_base);
+        } else if relative_base < current.relative_base {
+            while let Some(&base) = self.frame_bases.last() {
+                if base < relative_base {
+                    break;
+                }
+                self.frames.pop();
+                self.frame_bases.pop();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_program, Budget, ControlFlow, Event, Machine, OpcodeRegistry, ParamKind};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_profiler() {
+        // Grows the relative base, then jumps into a "function" at 9 which grows it again, and
+        // shrinks it on the way out
+        let program = "109,1,1105,1,9,109,-1,99,0,109,10,109,-10,1105,1,5";
+        let profiler = Rc::new(RefCell::new(Profiler::new()));
+        let mut machine = Machine::new(parse_program(program).unwrap());
+        machine.set_tracer(Box::new(Rc::clone(&profiler)));
+        assert_eq!(machine.run_until_output(), Ok(None));
+
+        let profiler = profiler.borrow();
+        assert_eq!(profiler.instructions(), 7);
+        assert_eq!(profiler.opcode_count(9), 4);
+        assert_eq!(profiler.address_count(9), 1);
+        assert_eq!(profiler.hottest_jumps(), vec![((2, 9), 1), ((13, 5), 1)]);
+
+        let mut folded = Vec::new();
+        profiler.write_folded(&mut folded).unwrap();
+        assert_eq!(
+            String::from_utf8(folded).unwrap(),
+            "main;frame@0 4\nmain 2\nmain;frame@0;frame@9 1\n"
+        );
+        assert!(profiler.report(10).contains("  ARB               4  57.14%"));
+
+        // Jumping to itself still counts as a jump
+        let profiler = Rc::new(RefCell::new(Profiler::new()));
+        let mut machine = Machine::new(parse_program("1105,1,0").unwrap());
+        machine.set_tracer(Box::new(Rc::clone(&profiler)));
+        machine.set_budget(Budget::instructions(3));
+        assert!(machine.run_until_output().is_err());
+        assert_eq!(profiler.borrow().hottest_jumps(), vec![((0, 0), 3)]);
+
+        // Neither a custom halt nor a custom instruction the profiler knows nothing about is a jump
+        let mut opcodes = OpcodeRegistry::standard();
+        opcodes.define(20, "NOP", &[ParamKind::Read, ParamKind::Read], ControlFlow::Next, |_| {
+            Ok(None)
+        });
+        opcodes.define(21, "STOP", &[], ControlFlow::Halt, |_| Ok(Some(Event::Halted)));
+        let profiler = Rc::new(RefCell::new(Profiler::new()));
+        let mut machine = Machine::new(parse_program("20,0,0,21").unwrap());
+        machine.set_opcodes(opcodes);
+        machine.set_tracer(Box::new(Rc::clone(&profiler)));
+        assert_eq!(machine.run_until_output(), Ok(None));
+        assert_eq!(profiler.borrow().instructions(), 2);
+        assert_eq!(profiler.borrow().hottest_jumps(), vec![]);
+    }
+}
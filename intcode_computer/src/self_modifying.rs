@@ -0,0 +1,357 @@
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::cfg::{control_flow_graph, decode};
+use crate::machine::{Opcode, ParameterMode};
+use crate::opcodes::OpcodeRegistry;
+use crate::trace::Tracer;
+
+/// A write by a program to a cell which also runs as part of an instruction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CodeWrite {
+    /// The address of the instruction which made the write.
+    pub writer: usize,
+    pub address: usize,
+    pub old_value: i64,
+    pub new_value: i64,
+    /// Whether the cell had already run when it was written, rather than running afterwards.
+    pub executed_before: bool,
+    /// Whether the cell runs as the opcode of an instruction, rather than a parameter.
+    pub is_opcode: bool,
+    /// The mnemonics of the old and new values, when the cell is an opcode and an instruction with
+    /// the same opcode had run by the time of the report.
+    pub old_mnemonic: Option<&'static str>,
+    pub new_mnemonic: Option<&'static str>,
+}
+
+fn describe_opcode(value: i64, mnemonic: Option<&str>) -> String {
+    match mnemonic {
+        Some(mnemonic) => format!("{} ({})", value, mnemonic),
+        None => value.to_string(),
+    }
+}
+
+impl fmt::Display for CodeWrite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let when = if self.executed_before {
+            "already ran"
+        } else {
+            "runs later"
+        };
+        if self.is_opcode {
+            write!(
+                f,
+                "Instruction at {} changed the opcode at {}, which {}, from {} to {}",
+                self.writer,
+                self.address,
+                when,
+                describe_opcode(self.old_value, self.old_mnemonic),
+                describe_opcode(self.new_value, self.new_mnemonic)
+            )
+        } else {
+            write!(
+                f,
+                "Instruction at {} changed the parameter at {}, which {}, from {} to {}",
+                self.writer, self.address, when, self.old_value, self.new_value
+            )
+        }
+    }
+}
+
+/// A tracer which reports writes to cells that run as code, either before or after the write.
+#[derive(Debug, Default)]
+pub struct SelfModificationDetector {
+    reports: Vec<CodeWrite>,
+    /// Cells which have run, and whether each was an opcode.
+    executed: HashMap<usize, bool>,
+    /// The writes to each cell which hasn't run yet, in order.
+    pending: HashMap<usize, Vec<CodeWrite>>,
+    ip: usize,
+    /// The mnemonic of each opcode which has run.
+    mnemonics: HashMap<u32, &'static str>,
+}
+
+impl SelfModificationDetector {
+    pub fn new() -> SelfModificationDetector {
+        SelfModificationDetector::default()
+    }
+
+    /// The writes found so far, in the order they were found.
+    pub fn reports(&self) -> &[CodeWrite] {
+        &self.reports
+    }
+
+    fn executed(&mut self, address: usize, is_opcode: bool) {
+        if self.executed.contains_key(&address) {
+            return;
+        }
+        self.executed.insert(address, is_opcode);
+        for write in self.pending.remove(&address).unwrap_or_default() {
+            self.report(write, is_opcode);
+        }
+    }
+
+    fn report(&mut self, write: CodeWrite, is_opcode: bool) {
+        let mnemonic = |value| {
+            let opcode = Opcode::from_value(value)?;
+            self.mnemonics.get(&opcode.code).copied()
+        };
+        let (old_mnemonic, new_mnemonic) = if is_opcode {
+            (mnemonic(write.old_value), mnemonic(write.new_value))
+        } else {
+            (None, None)
+        };
+        self.reports.push(CodeWrite {
+            is_opcode,
+            old_mnemonic,
+            new_mnemonic,
+            ..write
+        });
+    }
+}
+
+impl Tracer for SelfModificationDetector {
+    fn before_instruction(
+        &mut self,
+        ip: usize,
+        mnemonic: &'static str,
+        cells: &[i64],
+        _relative_base: i64,
+    ) {
+        self.ip = ip;
+        if let Some(opcode) = Opcode::from_value(cells[0]) {
+            self.mnemonics.insert(opcode.code, mnemonic);
+        }
+        self.executed(ip, true);
+        for address in ip + 1..ip + cells.len() {
+            self.executed(address, false);
+        }
+    }
+
+    fn on_write(&mut self, address: usize, old_value: i64, new_value: i64) {
+        let write = CodeWrite {
+            writer: self.ip,
+            address,
+            old_value,
+            new_value,
+            executed_before: true,
+            is_opcode: false,
+            old_mnemonic: None,
+            new_mnemonic: None,
+        };
+        match self.executed.get(&address) {
+            Some(&is_opcode) => self.report(write, is_opcode),
+            None => {
+                let write = CodeWrite {
+                    executed_before: false,
+                    ..write
+                };
+                self.pending.entry(address).or_default().push(write);
+            }
+        }
+    }
+}
+
+/// A reason why a program might modify its own code, so its instructions can't all be decoded
+/// before it runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PredecodeWarning {
+    /// The instruction at writer writes to a fixed address inside a reachable instruction.
+    WritesCode {
+        writer: usize,
+        address: usize,
+    },
+    /// The instruction at writer writes relative to the relative base, which could be anywhere.
+    UnknownWriteTarget {
+        writer: usize,
+    },
+    /// The jump at this address has a target read from memory, so code reached through it can't
+    /// be checked.
+    IndirectJump {
+        address: usize,
+    },
+}
+
+impl fmt::Display for PredecodeWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PredecodeWarning::WritesCode {
+                writer,
+                address,
+            } => {
+                write!(f, "Instruction at {} writes to code at {}", writer, address)
+            }
+            PredecodeWarning::UnknownWriteTarget {
+                writer,
+            } => write!(
+                f,
+                "Instruction at {} writes to an address which isn't known until it runs",
+                writer
+            ),
+            PredecodeWarning::IndirectJump {
+                address,
+            } => write!(
+                f,
+                "Jump at {} has a target which isn't known until it runs, so code it reaches isn't \
+                 checked",
+                address
+            ),
+        }
+    }
+}
+
+/// Scans a program without running it for anything that could stop its instructions being decoded
+/// once up front. An empty result means every write from reachable code lands outside it.
+pub fn check_predecode(program: &[i64], opcodes: &OpcodeRegistry) -> Vec<PredecodeWarning> {
+    let graph = control_flow_graph(program, opcodes);
+    // Reachable cells which aren't valid instructions may only become valid once written to
+    let mut code: HashSet<usize> =
+        graph.blocks.iter().flat_map(|block| block.start..block.end).collect();
+    code.extend(&graph.invalid);
+
+    let mut warnings = Vec::new();
+    for block in &graph.blocks {
+        for &address in &block.instructions {
+            check_writes(program, address, opcodes, &code, &mut warnings);
+        }
+        if block.indirect_jump {
+            let address = *block.instructions.last().unwrap();
+            warnings.push(PredecodeWarning::IndirectJump {
+                address,
+            });
+        }
+    }
+    warnings
+}
+
+/// Checks where the instruction at an address writes to, if it writes anywhere.
+fn check_writes(
+    program: &[i64],
+    address: usize,
+    opcodes: &OpcodeRegistry,
+    code: &HashSet<usize>,
+    warnings: &mut Vec<PredecodeWarning>,
+) {
+    let (opcode, info) = match decode(program, address, opcodes) {
+        Some(decoded) => decoded,
+        None => return,
+    };
+    for index in (0..info.param_count()).filter(|&index| info.writes(index)) {
+        match opcode.param_mode(index) {
+            ParameterMode::Position => match usize::try_from(program[address + 1 + index]) {
+                Ok(target) if code.contains(&target) => {
+                    warnings.push(PredecodeWarning::WritesCode {
+                        writer: address,
+                        address: target,
+                    })
+                }
+                _ => (),
+            },
+            ParameterMode::Relative => warnings.push(PredecodeWarning::UnknownWriteTarget {
+                writer: address,
+            }),
+            ParameterMode::Immediate => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_program, Machine};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_detects_code_writes() {
+        // Turns the halt at 10 into an output before it runs, and overwrites the output at 4 with
+        // a halt after it has run
+        let program = parse_program("1101,3,1,10,104,7,1101,0,99,4,99,10,99").unwrap();
+        let detector = Rc::new(RefCell::new(SelfModificationDetector::new()));
+        let mut machine = Machine::new(program);
+        machine.set_tracer(Box::new(Rc::clone(&detector)));
+        assert_eq!(machine.run_until_output(), Ok(Some(7)));
+        assert_eq!(machine.run_until_output(), Ok(Some(4)));
+        assert_eq!(machine.run_until_output(), Ok(None));
+
+        let detector = detector.borrow();
+        assert_eq!(
+            detector.reports(),
+            &[
+                CodeWrite {
+                    writer: 6,
+                    address: 4,
+                    old_value: 104,
+                    new_value: 99,
+                    executed_before: true,
+                    is_opcode: true,
+                    old_mnemonic: Some("OUT"),
+                    new_mnemonic: None,
+                },
+                CodeWrite {
+                    writer: 0,
+                    address: 10,
+                    old_value: 99,
+                    new_value: 4,
+                    executed_before: false,
+                    is_opcode: true,
+                    old_mnemonic: None,
+                    new_mnemonic: Some("OUT"),
+                },
+            ]
+        );
+        assert_eq!(
+            detector.reports()[1].to_string(),
+            "Instruction at 0 changed the opcode at 10, which runs later, from 99 to 4 (OUT)"
+        );
+    }
+
+    #[test]
+    fn test_reports_every_write_before_running() {
+        // Writes the parameter of the output at 8 twice before it runs
+        let program = parse_program("1101,1,2,9,1101,2,3,9,104,0,99").unwrap();
+        let detector = Rc::new(RefCell::new(SelfModificationDetector::new()));
+        let mut machine = Machine::new(program);
+        machine.set_tracer(Box::new(Rc::clone(&detector)));
+        assert_eq!(machine.run_until_output(), Ok(Some(5)));
+
+        let writes: Vec<(usize, i64, i64)> = detector
+            .borrow()
+            .reports()
+            .iter()
+            .map(|write| (write.writer, write.old_value, write.new_value))
+            .collect();
+        assert_eq!(writes, vec![(0, 0, 3), (4, 3, 5)]);
+    }
+
+    #[test]
+    fn test_check_predecode() {
+        let check =
+            |source| check_predecode(&parse_program(source).unwrap(), &OpcodeRegistry::standard());
+        assert_eq!(check("1101,1,1,5,99,0"), vec![]);
+        // The day 5 style fix-up of an invalid instruction before it runs
+        assert_eq!(
+            check("1101,1,1100,4,1100,99"),
+            vec![PredecodeWarning::WritesCode {
+                writer: 0,
+                address: 4,
+            }]
+        );
+        assert_eq!(
+            check("1101,1,98,5,104,0,21101,1,1,0,6,0,10,99"),
+            vec![
+                PredecodeWarning::WritesCode {
+                    writer: 0,
+                    address: 5,
+                },
+                PredecodeWarning::UnknownWriteTarget {
+                    writer: 6
+                },
+                PredecodeWarning::IndirectJump {
+                    address: 10
+                },
+            ]
+        );
+    }
+}
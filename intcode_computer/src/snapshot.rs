@@ -0,0 +1,210 @@
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::memory::Memory;
+use crate::word::Word;
+
+const HEADER: &str = "intcode-snapshot 1";
+
+/// The complete state of a machine: memory, registers and any input it hasn't consumed yet.
+///
+/// Snapshots are plain data, so they can be cloned to fork a machine many times or written to a
+/// file and restored later. Tracers aren't part of the snapshot.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot<W = i64> {
+    pub(crate) memory: Memory<W>,
+    pub(crate) ip: usize,
+    pub(crate) relative_base: W,
+    pub(crate) inputs: VecDeque<W>,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// The file isn't a valid snapshot. Holds the one-based line number and a description.
+    Format(usize, String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(why) => write!(f, "{}", why),
+            SnapshotError::Format(line, why) => write!(f, "Bad snapshot, line {}: {}", line, why),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(error: io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+fn join<T: ToString>(values: impl Iterator<Item = T>) -> String {
+    values.map(|value| value.to_string()).collect::<Vec<_>>().join(",")
+}
+
+impl<W: Word> Snapshot<W> {
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Snapshot<W>, SnapshotError> {
+        fs::read_to_string(path)?.parse()
+    }
+}
+
+/// Snapshots are written as text, one field per line, e.g.:
+///
+/// ```text
+/// intcode-snapshot 1
+/// ip 4
+/// relative_base 0
+/// inputs 5,6
+/// memory 1101,2,3,0,99
+/// sparse 100000=7
+/// ```
+impl<W: Word> fmt::Display for Snapshot<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut sparse: Vec<_> = self.memory.sparse().iter().collect();
+        sparse.sort_by_key(|(address, _)| **address);
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "ip {}", self.ip)?;
+        writeln!(f, "relative_base {}", self.relative_base)?;
+        writeln!(f, "inputs {}", join(self.inputs.iter()))?;
+        writeln!(f, "memory {}", join(self.memory.dense().iter()))?;
+        writeln!(
+            f,
+            "sparse {}",
+            join(sparse.iter().map(|(address, value)| format!("{}={}", address, value)))
+        )
+    }
+}
+
+fn parse_list<T: FromStr>(text: &str) -> Result<Vec<T>, String> {
+    if text.is_empty() {
+        return Ok(Vec::new());
+    }
+    text.split(',')
+        .map(|item| item.parse().map_err(|_| format!("'{}' is not a valid integer", item)))
+        .collect()
+}
+
+fn parse_sparse<W: Word>(text: &str) -> Result<HashMap<usize, W>, String> {
+    let mut sparse = HashMap::new();
+    for entry in text.split(',').filter(|entry| !entry.is_empty()) {
+        let mut parts = entry.splitn(2, '=');
+        let address = parts.next().and_then(|address| address.parse().ok());
+        let value = parts.next().and_then(|value| value.parse().ok());
+        match (address, value) {
+            (Some(address), Some(value)) => sparse.insert(address, value),
+            _ => return Err(format!("'{}' is not a valid address=value pair", entry)),
+        };
+    }
+    Ok(sparse)
+}
+
+impl<W: Word> FromStr for Snapshot<W> {
+    type Err = SnapshotError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut lines = text.lines();
+        if lines.next() != Some(HEADER) {
+            return Err(SnapshotError::Format(1, format!("Expected '{}'", HEADER)));
+        }
+
+        // Each remaining line must be the expected field, in order
+        let mut field = |line_number: usize, name: &str| {
+            let error = move |why| SnapshotError::Format(line_number, why);
+            let line = lines.next().ok_or_else(|| error(format!("Missing '{}'", name)))?;
+            match line.split_once(' ') {
+                Some((key, value)) if key == name => Ok((value.trim(), error)),
+                None if line == name => Ok(("", error)),
+                _ => Err(error(format!("Expected '{}'", name))),
+            }
+        };
+
+        let (ip, error) = field(2, "ip")?;
+        let ip = ip.parse().map_err(|_| error(format!("'{}' is not a valid address", ip)))?;
+        let (relative_base, error) = field(3, "relative_base")?;
+        let relative_base = relative_base
+            .parse()
+            .map_err(|_| error(format!("'{}' is not a valid integer", relative_base)))?;
+        let (inputs, error) = field(4, "inputs")?;
+        let inputs = parse_list(inputs).map_err(error)?;
+        let (dense, error) = field(5, "memory")?;
+        let dense = parse_list(dense).map_err(error)?;
+        let (sparse, error) = field(6, "sparse")?;
+        let sparse = parse_sparse(sparse).map_err(error)?;
+
+        Ok(Snapshot {
+            memory: Memory::from_parts(dense, sparse),
+            ip,
+            relative_base,
+            inputs: inputs.into_iter().collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_program, Event, Machine};
+
+    /// Reads two inputs and outputs their sum, after writing it far away in memory.
+    const ADDER: &str = "3,100,3,101,1,100,101,100000,4,100000,99";
+
+    #[test]
+    fn test_snapshot_text_round_trip() {
+        let mut machine = Machine::new(parse_program(ADDER).unwrap());
+        machine.push_input(2);
+        machine.push_input(3);
+        machine.push_input(4);
+        assert_eq!(machine.run_until_event(), Ok(Event::Output(5)));
+
+        let snapshot = machine.snapshot();
+        let text = snapshot.to_string();
+        assert!(text.contains("ip 10\n"));
+        assert!(text.contains("inputs 4\n"));
+        assert!(text.contains("sparse 100000=5\n"));
+        assert_eq!(text.parse::<Snapshot>().unwrap(), snapshot);
+
+        let path = std::env::temp_dir().join("intcode_snapshot_round_trip.txt");
+        snapshot.save(&path).unwrap();
+        assert_eq!(Snapshot::load(&path).unwrap(), snapshot);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_fork_from_snapshot() {
+        let mut machine = Machine::new(parse_program(ADDER).unwrap());
+        machine.push_input(10);
+        assert_eq!(machine.run_until_event(), Ok(Event::NeedsInput));
+        let checkpoint = machine.snapshot();
+
+        for second in 1..4 {
+            let mut fork = Machine::restore(checkpoint.clone());
+            fork.push_input(second);
+            assert_eq!(fork.run_until_output(), Ok(Some(10 + second)));
+        }
+    }
+
+    #[test]
+    fn test_bad_snapshots() {
+        let error = |text: &str| match text.parse::<Snapshot>() {
+            Err(SnapshotError::Format(line, _)) => line,
+            _ => panic!("Expected a format error"),
+        };
+        assert_eq!(error("hello"), 1);
+        assert_eq!(error("intcode-snapshot 1\nip x"), 2);
+        assert_eq!(error("intcode-snapshot 1\nip 0\nrelative_base 0\ninputs\nmemory 1,,2"), 5);
+        assert_eq!(error("intcode-snapshot 1\nip 0\nrelative_base 0\ninputs\nmemory 1"), 6);
+    }
+}
@@ -0,0 +1,393 @@
+use std::cell::RefCell;
+use std::io;
+use std::io::prelude::*;
+use std::rc::Rc;
+
+use crate::disasm::format_instruction;
+
+/// Hooks which a machine calls as it executes. Every hook has an empty default so tracers only
+/// need to implement the ones they care about. V is the machine's word type.
+pub trait Tracer<V = i64> {
+    /// Called before each instruction runs, with its mnemonic and cells: the opcode followed by
+    /// its parameters, as decoded by the machine's opcodes.
+    fn before_instruction(
+        &mut self,
+        _ip: usize,
+        _mnemonic: &'static str,
+        _cells: &[V],
+        _relative_base: V,
+    ) {
+    }
+
+    /// Called after an instruction has run successfully, with the updated registers and whether
+    /// it jumped rather than moving on to the next instruction.
+    fn after_instruction(&mut self, _ip: usize, _relative_base: V, _jumped: bool) {}
+
+    /// Called whenever the program writes to memory.
+    fn on_write(&mut self, _address: usize, _old_value: V, _new_value: V) {}
+
+    /// Called once an instruction has run, for each value it read from memory as a parameter.
+    fn on_read(&mut self, _address: usize, _value: V) {}
+
+    fn on_input(&mut self, _value: V) {}
+
+    fn on_output(&mut self, _value: V) {}
+}
+
+/// Lets a tracer be shared with a machine, so its results can still be read once it's attached.
+impl<V, T: Tracer<V>> Tracer<V> for Rc<RefCell<T>> {
+    fn before_instruction(
+        &mut self,
+        ip: usize,
+        mnemonic: &'static str,
+        cells: &[V],
+        relative_base: V,
+    ) {
+        self.borrow_mut().before_instruction(ip, mnemonic, cells, relative_base);
+    }
+
+    fn after_instruction(&mut self, ip: usize, relative_base: V, jumped: bool) {
+        self.borrow_mut().after_instruction(ip, relative_base, jumped);
+    }
+
+    fn on_write(&mut self, address: usize, old_value: V, new_value: V) {
+        self.borrow_mut().on_write(address, old_value, new_value);
+    }
+
+    fn on_read(&mut self, address: usize, value: V) {
+        self.borrow_mut().on_read(address, value);
+    }
+
+    fn on_input(&mut self, value: V) {
+        self.borrow_mut().on_input(value);
+    }
+
+    fn on_output(&mut self, value: V) {
+        self.borrow_mut().on_output(value);
+    }
+}
+
+/// Passes everything on to several tracers in turn, so more than one can be attached to a machine.
+impl<V: Clone> Tracer<V> for Vec<Box<dyn Tracer<V>>> {
+    fn before_instruction(
+        &mut self,
+        ip: usize,
+        mnemonic: &'static str,
+        cells: &[V],
+        relative_base: V,
+    ) {
+        for tracer in self.iter_mut() {
+            tracer.before_instruction(ip, mnemonic, cells, relative_base.clone());
+        }
+    }
+
+    fn after_instruction(&mut self, ip: usize, relative_base: V, jumped: bool) {
+        for tracer in self.iter_mut() {
+            tracer.after_instruction(ip, relative_base.clone(), jumped);
+        }
+    }
+
+    fn on_write(&mut self, address: usize, old_value: V, new_value: V) {
+        for tracer in self.iter_mut() {
+            tracer.on_write(address, old_value.clone(), new_value.clone());
+        }
+    }
+
+    fn on_read(&mut self, address: usize, value: V) {
+        for tracer in self.iter_mut() {
+            tracer.on_read(address, value.clone());
+        }
+    }
+
+    fn on_input(&mut self, value: V) {
+        for tracer in self.iter_mut() {
+            tracer.on_input(value.clone());
+        }
+    }
+
+    fn on_output(&mut self, value: V) {
+        for tracer in self.iter_mut() {
+            tracer.on_output(value.clone());
+        }
+    }
+}
+
+/// Writes a human-readable line for each instruction and its side effects.
+pub struct PrintTracer<W: Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> PrintTracer<W> {
+    pub fn new(writer: W) -> Self {
+        PrintTracer {
+            writer,
+            error: None,
+        }
+    }
+
+    /// Returns the writer, or the first error that occurred while writing to it.
+    pub fn finish(self) -> io::Result<W> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.writer),
+        }
+    }
+
+    fn print(&mut self, line: String) {
+        if self.error.is_none() {
+            if let Err(error) = writeln!(self.writer, "{}", line) {
+                self.error = Some(error);
+            }
+        }
+    }
+}
+
+impl<W: Write> Tracer for PrintTracer<W> {
+    fn before_instruction(
+        &mut self,
+        ip: usize,
+        mnemonic: &'static str,
+        cells: &[i64],
+        relative_base: i64,
+    ) {
+        let text = format_instruction(mnemonic, cells);
+        self.print(format!("{:5}: {:<30} rb={}", ip, text, relative_base));
+    }
+
+    fn on_write(&mut self, address: usize, old_value: i64, new_value: i64) {
+        self.print(format!("       [{}] {} -> {}", address, old_value, new_value));
+    }
+
+    fn on_input(&mut self, value: i64) {
+        self.print(format!("       input {}", value));
+    }
+
+    fn on_output(&mut self, value: i64) {
+        self.print(format!("       output {}", value));
+    }
+}
+
+/// An event recorded by a BinaryTracer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceRecord {
+    Instruction {
+        ip: usize,
+        instruction: i64,
+        relative_base: i64,
+    },
+    Write {
+        address: usize,
+        old_value: i64,
+        new_value: i64,
+    },
+    Input(i64),
+    Output(i64),
+}
+
+const INSTRUCTION_TAG: u8 = 1;
+const WRITE_TAG: u8 = 2;
+const INPUT_TAG: u8 = 3;
+const OUTPUT_TAG: u8 = 4;
+
+/// Writes a compact binary trace: each record is a one-byte tag followed by its fields as
+/// little-endian 64-bit integers. Read it back with read_binary_trace.
+pub struct BinaryTracer<W: Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> BinaryTracer<W> {
+    pub fn new(writer: W) -> Self {
+        BinaryTracer {
+            writer,
+            error: None,
+        }
+    }
+
+    /// Returns the writer, or the first error that occurred while writing to it.
+    pub fn finish(self) -> io::Result<W> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.writer),
+        }
+    }
+
+    fn record(&mut self, tag: u8, fields: &[i64]) {
+        if self.error.is_some() {
+            return;
+        }
+        let mut bytes = vec![tag];
+        for field in fields {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        if let Err(error) = self.writer.write_all(&bytes) {
+            self.error = Some(error);
+        }
+    }
+}
+
+impl<W: Write> Tracer for BinaryTracer<W> {
+    fn before_instruction(
+        &mut self,
+        ip: usize,
+        _mnemonic: &'static str,
+        cells: &[i64],
+        relative_base: i64,
+    ) {
+        self.record(INSTRUCTION_TAG, &[ip as i64, cells[0], relative_base]);
+    }
+
+    fn on_write(&mut self, address: usize, old_value: i64, new_value: i64) {
+        self.record(WRITE_TAG, &[address as i64, old_value, new_value]);
+    }
+
+    fn on_input(&mut self, value: i64) {
+        self.record(INPUT_TAG, &[value]);
+    }
+
+    fn on_output(&mut self, value: i64) {
+        self.record(OUTPUT_TAG, &[value]);
+    }
+}
+
+fn read_field(reader: &mut impl Read) -> io::Result<i64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(i64::from_le_bytes(bytes))
+}
+
+/// Reads back a trace written by a BinaryTracer.
+pub fn read_binary_trace(mut reader: impl Read) -> io::Result<Vec<TraceRecord>> {
+    let mut records = Vec::new();
+    let mut tag = [0];
+    while reader.read(&mut tag)? == 1 {
+        let record = match tag[0] {
+            INSTRUCTION_TAG => TraceRecord::Instruction {
+                ip: read_field(&mut reader)? as usize,
+                instruction: read_field(&mut reader)?,
+                relative_base: read_field(&mut reader)?,
+            },
+            WRITE_TAG => TraceRecord::Write {
+                address: read_field(&mut reader)? as usize,
+                old_value: read_field(&mut reader)?,
+                new_value: read_field(&mut reader)?,
+            },
+            INPUT_TAG => TraceRecord::Input(read_field(&mut reader)?),
+            OUTPUT_TAG => TraceRecord::Output(read_field(&mut reader)?),
+            tag => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Unknown trace record tag {}", tag),
+                ))
+            }
+        };
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_program, Machine};
+
+    /// A writer whose contents can still be read after it's been handed to a machine.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run_traced(tracer: Box<dyn Tracer>) {
+        // Adds 2 to its input and outputs the result
+        let mut machine = Machine::new(parse_program("3,9,1001,9,2,9,4,9,99,0").unwrap());
+        machine.set_tracer(tracer);
+        machine.push_input(40);
+        assert_eq!(machine.run_until_output(), Ok(Some(42)));
+        assert_eq!(machine.run_until_output(), Ok(None));
+    }
+
+    #[test]
+    fn test_print_tracer() {
+        let buffer = SharedBuffer::default();
+        run_traced(Box::new(PrintTracer::new(buffer.clone())));
+        let trace = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        assert_eq!(
+            trace.lines().collect::<Vec<_>>(),
+            vec![
+                "    0: IN [9]                         rb=0",
+                "       input 40",
+                "       [9] 0 -> 40",
+                "    2: ADD [9], #2, [9]               rb=0",
+                "       [9] 40 -> 42",
+                "    6: OUT [9]                        rb=0",
+                "       output 42",
+                "    8: HLT                            rb=0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_binary_trace_round_trip() {
+        let buffer = SharedBuffer::default();
+        run_traced(Box::new(BinaryTracer::new(buffer.clone())));
+        let bytes = buffer.0.borrow().clone();
+        assert_eq!(
+            read_binary_trace(bytes.as_slice()).unwrap(),
+            vec![
+                TraceRecord::Instruction {
+                    ip: 0,
+                    instruction: 3,
+                    relative_base: 0
+                },
+                TraceRecord::Input(40),
+                TraceRecord::Write {
+                    address: 9,
+                    old_value: 0,
+                    new_value: 40
+                },
+                TraceRecord::Instruction {
+                    ip: 2,
+                    instruction: 1001,
+                    relative_base: 0
+                },
+                TraceRecord::Write {
+                    address: 9,
+                    old_value: 40,
+                    new_value: 42
+                },
+                TraceRecord::Instruction {
+                    ip: 6,
+                    instruction: 4,
+                    relative_base: 0
+                },
+                TraceRecord::Output(42),
+                TraceRecord::Instruction {
+                    ip: 8,
+                    instruction: 99,
+                    relative_base: 0
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_several_tracers() {
+        let (printed, binary) = (SharedBuffer::default(), SharedBuffer::default());
+        let tracers: Vec<Box<dyn Tracer>> = vec![
+            Box::new(PrintTracer::new(printed.clone())),
+            Box::new(BinaryTracer::new(binary.clone())),
+        ];
+        run_traced(Box::new(tracers));
+        assert_eq!(String::from_utf8(printed.0.borrow().clone()).unwrap().lines().count(), 8);
+        assert_eq!(read_binary_trace(binary.0.borrow().as_slice()).unwrap().len(), 8);
+    }
+}
@@ -0,0 +1,137 @@
+use std::ops::RangeInclusive;
+
+/// The kind of memory access a watchpoint triggers on. Only accesses the program makes through
+/// its parameters count, not fetching instructions or reads and writes made by the caller.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    /// A write which changes the value in the cell.
+    Change,
+}
+
+type Callback<W> = Box<dyn FnMut(&WatchHit<W>)>;
+
+/// What happens when a watchpoint triggers.
+pub enum WatchAction<W = i64> {
+    /// Stop running once the instruction has finished, returning `Event::Watchpoint`.
+    Pause,
+    /// Call a function and carry on.
+    Callback(Callback<W>),
+}
+
+/// A watchpoint being triggered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchHit<W = i64> {
+    /// The id returned when the watchpoint was added.
+    pub id: usize,
+    /// The address of the instruction which made the access.
+    pub ip: usize,
+    pub address: usize,
+    pub access: Access,
+    /// The value before the access. For reads this is the same as new_value.
+    pub old_value: W,
+    pub new_value: W,
+}
+
+pub(crate) struct Watchpoint<W> {
+    pub(crate) id: usize,
+    pub(crate) addresses: RangeInclusive<usize>,
+    pub(crate) access: Access,
+    pub(crate) action: WatchAction<W>,
+}
+
+/// A read or write of one cell, recorded while an instruction runs so that watchpoints can be
+/// checked once it's finished.
+pub(crate) struct MemoryAccess<W> {
+    pub(crate) address: usize,
+    /// None for reads.
+    pub(crate) old_value: Option<W>,
+    pub(crate) new_value: W,
+}
+
+impl<W: PartialEq + Clone> Watchpoint<W> {
+    /// Checks whether an access triggers this watchpoint, and if so runs its callback or returns
+    /// the hit to pause on.
+    pub(crate) fn check(&mut self, ip: usize, access: &MemoryAccess<W>) -> Option<WatchHit<W>> {
+        if !self.addresses.contains(&access.address) {
+            return None;
+        }
+        let triggered = match (&access.old_value, self.access) {
+            (None, Access::Read) | (Some(_), Access::Write) => true,
+            (Some(old_value), Access::Change) => *old_value != access.new_value,
+            _ => false,
+        };
+        if !triggered {
+            return None;
+        }
+
+        let hit = WatchHit {
+            id: self.id,
+            ip,
+            address: access.address,
+            access: self.access,
+            old_value: access.old_value.clone().unwrap_or_else(|| access.new_value.clone()),
+            new_value: access.new_value.clone(),
+        };
+        match &mut self.action {
+            WatchAction::Pause => Some(hit),
+            WatchAction::Callback(callback) => {
+                callback(&hit);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_program, Event, Machine};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Counts down cell 12 from 3 to 0, outputting cell 13 each time round.
+    const COUNTDOWN: &str = "1001,12,-1,12,4,13,1005,12,0,99,0,0,3,42";
+
+    #[test]
+    fn test_pause_on_change() {
+        let mut machine = Machine::new(parse_program(COUNTDOWN).unwrap());
+        let id = machine.add_watchpoint(12..=12, Access::Change, WatchAction::Pause);
+        let hit = |old_value, new_value| {
+            Ok(Event::Watchpoint(WatchHit {
+                id,
+                ip: 0,
+                address: 12,
+                access: Access::Change,
+                old_value,
+                new_value,
+            }))
+        };
+        assert_eq!(machine.run_until_event(), hit(3, 2));
+        assert_eq!(machine.ip(), 4);
+        assert_eq!(machine.run_until_event(), Ok(Event::Output(42)));
+        assert_eq!(machine.run_until_event(), hit(2, 1));
+
+        assert!(machine.remove_watchpoint(id));
+        assert_eq!(machine.run_until_output(), Ok(Some(42)));
+        assert_eq!(machine.run_until_output(), Ok(Some(42)));
+        assert_eq!(machine.run_until_output(), Ok(None));
+    }
+
+    #[test]
+    fn test_callback_on_read() {
+        let reads = Rc::new(RefCell::new(Vec::new()));
+        let recorded = Rc::clone(&reads);
+        let mut machine = Machine::new(parse_program(COUNTDOWN).unwrap());
+        machine.add_watchpoint(
+            12..=13,
+            Access::Read,
+            WatchAction::Callback(Box::new(move |hit| {
+                recorded.borrow_mut().push((hit.ip, hit.address, hit.new_value))
+            })),
+        );
+        assert_eq!(machine.run_until_output(), Ok(Some(42)));
+        assert_eq!(*reads.borrow(), vec![(0, 12, 3), (4, 13, 42)]);
+    }
+}
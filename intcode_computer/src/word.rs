@@ -0,0 +1,113 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::{Add, Mul};
+use std::str::FromStr;
+
+/// The type of value held in each memory cell of a machine.
+///
+/// Intcode only needs integers which can be added, multiplied and compared, so a machine can be
+/// run at whatever precision a program needs: i64 for most puzzles, i128 for programs that
+/// overflow it and BigInt when nothing fixed-size will do. Default must give zero.
+pub trait Word:
+    'static
+    + Clone
+    + fmt::Debug
+    + fmt::Display
+    + FromStr
+    + Default
+    + PartialEq
+    + PartialOrd
+    + Add<Output = Self>
+    + Mul<Output = Self>
+{
+    fn from_i64(value: i64) -> Self;
+
+    /// Returns None if the value doesn't fit in the type.
+    fn from_u128(value: u128) -> Option<Self>;
+
+    /// Returns None if the value doesn't fit in an i64.
+    fn to_i64(&self) -> Option<i64>;
+
+    /// Returns None if the value is negative or too large to be an address.
+    fn to_usize(&self) -> Option<usize>;
+
+    /// Returns None if the result would overflow.
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+
+    /// Returns None if the result would overflow.
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+
+    /// Wraps around at the bounds of the type on overflow.
+    fn wrapping_add(&self, other: &Self) -> Self;
+
+    /// Wraps around at the bounds of the type on overflow.
+    fn wrapping_mul(&self, other: &Self) -> Self;
+}
+
+impl Word for i64 {
+    fn from_i64(value: i64) -> Self {
+        value
+    }
+
+    fn from_u128(value: u128) -> Option<Self> {
+        i64::try_from(value).ok()
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        Some(*self)
+    }
+
+    fn to_usize(&self) -> Option<usize> {
+        usize::try_from(*self).ok()
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        i64::checked_add(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        i64::checked_mul(*self, *other)
+    }
+
+    fn wrapping_add(&self, other: &Self) -> Self {
+        i64::wrapping_add(*self, *other)
+    }
+
+    fn wrapping_mul(&self, other: &Self) -> Self {
+        i64::wrapping_mul(*self, *other)
+    }
+}
+
+impl Word for i128 {
+    fn from_i64(value: i64) -> Self {
+        i128::from(value)
+    }
+
+    fn from_u128(value: u128) -> Option<Self> {
+        i128::try_from(value).ok()
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        i64::try_from(*self).ok()
+    }
+
+    fn to_usize(&self) -> Option<usize> {
+        usize::try_from(*self).ok()
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        i128::checked_add(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        i128::checked_mul(*self, *other)
+    }
+
+    fn wrapping_add(&self, other: &Self) -> Self {
+        i128::wrapping_add(*self, *other)
+    }
+
+    fn wrapping_mul(&self, other: &Self) -> Self {
+        i128::wrapping_mul(*self, *other)
+    }
+}